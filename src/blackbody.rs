@@ -0,0 +1,163 @@
+//! Thermal emission for the `blackbody` accretion disk, and the colours of
+//! the `stars` background.
+//!
+//! The disk temperature follows the Novikov-Thorne (Page-Thorne) flux for a
+//! thin disk around a Kerr hole, and temperatures are turned into linear
+//! sRGB by integrating the Planck spectrum against the CIE 1931 colour
+//! matching functions.  Both are tabulated here once and shared by the
+//! shader generator and the CPU tracer.
+
+use std::f64::consts::PI;
+
+use kerr;
+use scene::SceneConfig;
+
+/// Number of radii the disk temperature is tabulated at.
+pub const TEMP_SAMPLES: usize = 64;
+/// Number of temperatures the blackbody colour is tabulated at.
+pub const COL_SAMPLES: usize = 128;
+/// The colour table covers temperatures from `10^LOG_T_MIN` to
+/// `10^LOG_T_MAX` kelvin, evenly spaced in `log10(T)`.
+pub const LOG_T_MIN: f32 = 3.0;
+pub const LOG_T_MAX: f32 = 9.0;
+
+const G: f64 = 6.674e-11;
+const C: f64 = 2.998e8;
+const H: f64 = 6.626e-34;
+const K_B: f64 = 1.381e-23;
+const SIGMA: f64 = 5.670e-8;
+const M_SUN: f64 = 1.989e30;
+/// Eddington luminosity per solar mass, in watts.
+const L_EDD_SUN: f64 = 1.26e31;
+/// Radiative efficiency used to turn the Eddington luminosity into an
+/// accretion rate.
+const EFFICIENCY: f64 = 0.1;
+
+pub struct Disk {
+    /// Temperature in kelvin at evenly spaced radii from the inner to the
+    /// outer edge of the disk.
+    pub temps: Vec<f32>,
+    /// Linear sRGB colour of a blackbody at each tabulated temperature,
+    /// scaled so that the hottest point of the disk has a luminance of 1.
+    pub colours: Vec<[f32; 3]>,
+}
+
+impl Disk {
+    pub fn new(scene: &SceneConfig) -> Self {
+        let mass = scene.mass as f64;
+        let mdot = scene.mdot as f64;
+        let (i_rad, o_rad) = (scene.inner_radius(), scene.outer_radius);
+        let spin = scene.spin();
+        let a = kerr::spin_length(spin);
+
+        /* geometric units: lengths are measured in GM/c^2 */
+        let r_g = G * mass * M_SUN / (C * C);
+        let mdot = mdot * L_EDD_SUN * mass / (EFFICIENCY * C * C);
+        let x0 = (kerr::isco(spin) / kerr::M).sqrt() as f64;
+
+        let temps: Vec<f32> = (0..TEMP_SAMPLES)
+            .map(|i| {
+                let mag = i_rad + (o_rad - i_rad) * i as f32 / (TEMP_SAMPLES - 1) as f32;
+                /* the Boyer-Lindquist radius of a point in the disk plane */
+                let r = (mag * mag - a * a).max(0.0).sqrt();
+                let x = (r / kerr::M).sqrt() as f64;
+                let flux = mdot * C * C / (r_g * r_g) * page_thorne(spin as f64, x, x0);
+                (flux.max(0.0) / SIGMA).powf(0.25) as f32
+            })
+            .collect();
+
+        let t_max = temps.iter().cloned().fold(0.0f32, f32::max);
+        let norm = if t_max > 0.0 { 1.0 / luminance(t_max as f64) } else { 1.0 };
+        let colours = (0..COL_SAMPLES)
+            .map(|i| {
+                let log_t = LOG_T_MIN + (LOG_T_MAX - LOG_T_MIN) * i as f32 / (COL_SAMPLES - 1) as f32;
+                let rgb = rgb(10f64.powf(log_t as f64));
+                [(rgb[0] * norm) as f32, (rgb[1] * norm) as f32, (rgb[2] * norm) as f32]
+            })
+            .collect();
+
+        Disk {
+            temps: temps,
+            colours: colours,
+        }
+    }
+}
+
+/// The dimensionless Page-Thorne flux `F r_g^2 / (mdot c^2)` at
+/// `x = sqrt(r / M)` for a disk with its inner edge at `x0`.
+fn page_thorne(spin: f64, x: f64, x0: f64) -> f64 {
+    if x <= x0 {
+        return 0.0;
+    }
+    let a = spin;
+    /* roots of x^3 - 3x + 2a = 0 */
+    let x1 = 2.0 * ((a.acos() - PI) / 3.0).cos();
+    let x2 = 2.0 * ((a.acos() + PI) / 3.0).cos();
+    let x3 = -2.0 * (a.acos() / 3.0).cos();
+
+    let term = |xi: f64, xj: f64, xk: f64| {
+        if xi.abs() < 1e-9 {
+            /* only happens for a = 0, where the coefficient goes to 0 */
+            return 0.0;
+        }
+        3.0 * (xi - a) * (xi - a) / (xi * (xi - xj) * (xi - xk)) * ((x - xi) / (x0 - xi)).ln()
+    };
+
+    let bracket = x - x0 - 1.5 * a * (x / x0).ln() - term(x1, x2, x3) - term(x2, x1, x3) - term(x3, x1, x2);
+    let f = 1.5 / (x * x * (x * x * x - 3.0 * x + 2.0 * a)) * bracket;
+    f / (4.0 * PI * x * x)
+}
+
+/// Planck's law for the spectral radiance at a wavelength in metres.
+fn planck(lambda: f64, t: f64) -> f64 {
+    2.0 * H * C * C / lambda.powi(5) / ((H * C / (lambda * K_B * t)).exp() - 1.0)
+}
+
+/// The CIE 1931 colour matching functions at a wavelength in nanometres,
+/// using the multi-lobe fit of Wyman, Sloan and Shirley (2013).
+fn cie_xyz(lambda: f64) -> [f64; 3] {
+    let g = |mu: f64, s1: f64, s2: f64| {
+        let s = if lambda < mu { s1 } else { s2 };
+        (-0.5 * (lambda - mu) * (lambda - mu) / (s * s)).exp()
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+fn xyz(t: f64) -> [f64; 3] {
+    let mut sum = [0.0; 3];
+    let mut lambda = 380.0;
+    while lambda <= 780.0 {
+        let b = planck(lambda * 1e-9, t);
+        let cmf = cie_xyz(lambda);
+        for i in 0..3 {
+            sum[i] += b * cmf[i] * 5.0;
+        }
+        lambda += 5.0;
+    }
+    sum
+}
+
+fn luminance(t: f64) -> f64 {
+    xyz(t)[1]
+}
+
+/// Linear sRGB colour of a blackbody, scaled to a luminance of 1.
+pub fn chromaticity(t: f64) -> [f32; 3] {
+    let rgb = rgb(t);
+    let norm = 1.0 / luminance(t);
+    [(rgb[0] * norm) as f32, (rgb[1] * norm) as f32, (rgb[2] * norm) as f32]
+}
+
+/// Linear sRGB colour of a blackbody, with out of gamut channels clipped.
+fn rgb(t: f64) -> [f64; 3] {
+    let [x, y, z] = xyz(t);
+    [
+        (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+        (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+    ]
+}
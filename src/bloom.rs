@@ -0,0 +1,46 @@
+//! Glare around the brightest parts of the image, such as the inner disk,
+//! added between tracing and tone mapping.
+//!
+//! Whatever in a pixel exceeds `THRESHOLD` is blurred with a Gaussian, one
+//! axis at a time, and the result scaled by the strength `bloom` is added
+//! back to the traced colours.  Each eye of a stereo view is blurred on its
+//! own so that no light crosses between the views.
+
+/// Colours are thresholded by their largest component, so that glare keeps
+/// the hue of what casts it.
+pub const THRESHOLD: f32 = 1.0;
+
+/// The blur of a view `height` pixels high, for a `bloom_radius` of
+/// `radius`.
+pub struct Kernel {
+    /// Standard deviation in pixels, at least half a pixel.
+    pub sigma: f32,
+    /// How many pixels either side of the centre the kernel reaches.
+    pub reach: i32,
+}
+
+impl Kernel {
+    pub fn new(radius: f32, height: u32) -> Self {
+        let sigma = (radius * height as f32).max(0.5);
+        Kernel {
+            sigma: sigma,
+            reach: (3.0 * sigma).ceil() as i32,
+        }
+    }
+
+    /// Weight of the pixel `i` away from the centre, before normalising.
+    pub fn weight(&self, i: i32) -> f32 {
+        (-(i * i) as f32 / (2.0 * self.sigma * self.sigma)).exp()
+    }
+}
+
+/// What of `c` is bright enough to glare, zero below `threshold`.
+pub fn bright(c: [f32; 3], threshold: f32) -> [f32; 3] {
+    let m = c[0].max(c[1]).max(c[2]);
+    if m > threshold {
+        let s = 1.0 - threshold / m;
+        [c[0] * s, c[1] * s, c[2] * s]
+    } else {
+        [0.0; 3]
+    }
+}
@@ -0,0 +1,83 @@
+use cgmath::{vec3, Euler, InnerSpace, Matrix3, Vector3};
+use glium::glutin::VirtualKeyCode;
+
+use std::collections::HashSet;
+use std::fmt;
+
+pub struct Camera {
+    pub pos: Vector3<f32>,
+    /// Rotates world directions into the camera's frame, whose right, up
+    /// and forward directions are x, y and z.
+    pub facing: Matrix3<f32>,
+}
+
+impl Camera {
+    /// A camera at `pos` looking towards `target`, with `up` pointing up the
+    /// view as far as it can.
+    pub fn look_at(pos: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) -> Self {
+        Camera {
+            pos: pos,
+            facing: Matrix3::look_at((target - pos).normalize(), up.normalize()),
+        }
+    }
+
+    /// Flies the camera for `dt` seconds with the keys held: WASD, Q and E
+    /// move it, IJKL, U and O turn it, and shift speeds it up.
+    pub fn update(&mut self, keys: &HashSet<VirtualKeyCode>, dt: f32) {
+        use cgmath::Rad ;
+        use cgmath::SquareMatrix;
+        use cgmath::Zero;
+
+        let ang = Rad::<f32>(1f32 * dt);
+        let mut dist = 0.2f32;
+
+        let mut vert = 0.0;
+        let mut hori = 0.0;
+        let mut depth = 0.0;
+        let mut yaw = Rad::zero();
+        let mut pitch = Rad::zero();
+        let mut roll = Rad::zero();
+        for &k in keys {
+            match k {
+                VirtualKeyCode::W => vert += 1.0,
+                VirtualKeyCode::S => vert -= 1.0,
+                VirtualKeyCode::A => hori -= 1.0,
+                VirtualKeyCode::D => hori += 1.0,
+                VirtualKeyCode::Q => depth -= 1.0,
+                VirtualKeyCode::E => depth += 1.0,
+                VirtualKeyCode::LShift => dist = 2f32,
+                VirtualKeyCode::I => pitch = pitch - ang,
+                VirtualKeyCode::K => pitch = pitch + ang,
+                VirtualKeyCode::J => yaw = yaw - ang,
+                VirtualKeyCode::L => yaw = yaw + ang,
+                VirtualKeyCode::U => roll = roll + ang,
+                VirtualKeyCode::O => roll = roll - ang,
+                _ => (),
+            }
+        }
+
+        let mov = self.facing.invert().unwrap() * vec3(hori, vert, depth);
+        let rot = Matrix3::from(Euler {x: pitch, y: yaw, z: roll});
+
+        self.pos = self.pos + mov * dist * dt;
+        self.facing = (self.facing.invert().unwrap() * rot).invert().unwrap();
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            pos: vec3(0.0, 0.0, -10.0f32),
+            facing: Matrix3::look_at(vec3(0., 0., 1.), vec3(0., 1., 0.)),
+        }
+    }
+}
+
+impl fmt::Display for Camera {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pos = self.pos;
+        let fw = self.facing.z;
+        let up = self.facing.y;
+        write!(f, "pos: {:?} dir: {:?} up: {:?}", pos, fw, up)
+    }
+}
@@ -0,0 +1,1340 @@
+//! A pure-Rust reference implementation of the tracer in `shaders`.
+//!
+//! Each module here mirrors the GLSL snippet of the same name in
+//! `shaders::frag_shader`, so the output can be compared with the GPU render
+//! and images can be produced on machines without any GL implementation.
+//! Changes to the shader code should be reflected here.
+
+extern crate cgmath;
+extern crate image;
+
+use cgmath::{vec3, vec4, InnerSpace, Matrix, Vector3, Vector4};
+
+use std::f32;
+use std::thread;
+
+use error::Result;
+use kerr;
+use output::{self, Aovs, Frame};
+use resources;
+use scene::{Eye, Projection, SceneConfig, Stereo};
+
+use Camera;
+
+pub struct Tracer {
+    time_step: f32,
+    projection: Projection,
+    stereo: Stereo,
+    ipd: f32,
+    /// See `SceneConfig::subpixels`.
+    subpixels: Vec<(f32, f32)>,
+    fov_ratio: f32,
+    spin: f32,
+    horizon: f32,
+    method: trace::Type,
+    surface: bh::Surface,
+    disk: ad::Disk,
+    bg: bg::Background,
+    tone_map: tone::ToneMap,
+    glare: glare::Glare,
+    aov: bool,
+    drift: bool,
+    hdr: bool,
+}
+
+/// A rendered row of the linear colours, and of the AOVs if they were asked
+/// for.
+struct Row {
+    colours: Vec<Vector4<f32>>,
+    aovs: Vec<f32>,
+}
+
+impl Tracer {
+    /// Loads the textures and tables for `scene`, failing if it doesn't
+    /// validate.
+    pub fn new(scene: &SceneConfig) -> Result<Self> {
+        scene.validate()?;
+        let spin = scene.spin();
+        Ok(Tracer {
+            time_step: scene.time_step,
+            projection: scene.projection,
+            stereo: scene.stereo,
+            ipd: scene.ipd,
+            subpixels: scene.subpixels(),
+            fov_ratio: (scene.fov / 2.0f32 / 180.0f32 * f32::consts::PI).tan(),
+            spin: kerr::spin_length(spin),
+            horizon: kerr::horizon(spin),
+            method: trace::get_type(scene),
+            surface: bh::get_surface(scene),
+            disk: ad::Disk::new(scene)?,
+            bg: bg::Background::new(scene)?,
+            tone_map: tone::ToneMap::new(scene),
+            glare: glare::Glare::new(scene),
+            aov: scene.aovs(),
+            drift: scene.drift,
+            hdr: scene.hdr,
+        })
+    }
+
+    /// Follows a single ray starting at `origin`, for a camera at `src`,
+    /// returning its linear colour and its AOVs in `output::CHANNELS` order.
+    /// This is the body of `main` in the fragment shader.
+    pub fn trace(
+        &self,
+        src: Vector3<f32>,
+        origin: Vector3<f32>,
+        dir: Vector3<f32>,
+        time: f32,
+    ) -> (Vector4<f32>, [f32; output::CHANNELS.len()]) {
+        let mut alpha_rem = 1.0f32;
+        let mut ccolor = vec4(0.0, 0.0, 0.0, 0.0);
+        let mut dir = dir.normalize();
+        let mut pos = origin;
+
+        let mut vars = self.method.vars(self.spin, self.time_step, pos, dir);
+
+        let mut iterations = 0;
+        let mut hit = output::HIT_ESCAPED;
+        let mut disk_radius = 0.0;
+        let (mut crossings, mut disk_order) = (0, -1.0);
+        let (mut shift_sum, mut shift_weight) = (0.0f32, 0.0f32);
+        let (mut drift_h, mut drift_null) = (0.0f32, 0.0f32);
+        let invariants = |pos: Vector3<f32>, dir: Vector3<f32>| {
+            let h = pos.cross(dir);
+            (h, dir.dot(dir) - h.dot(h) / pos.dot(pos).powf(1.5))
+        };
+        let (h0, null0) = invariants(pos, dir);
+
+        let border = (15.0f32 * 15.0).max(src.dot(src));
+        while pos.dot(pos) <= border && alpha_rem >= 0.01 {
+            let (npos, ndir) =
+                self.method.update(&mut vars, self.spin, self.time_step, border, &mut pos, dir);
+
+            if let Some(closest) = bh::check(self.spin, self.horizon, pos, npos) {
+                let colour = self.surface.colour(closest);
+                hit = output::HIT_HORIZON;
+                ccolor += colour * alpha_rem * 1.0;
+                alpha_rem -= alpha_rem * 1.0;
+            }
+            if let Some(crossing) = self.disk.check(pos, npos, time, vars.photon_l, src, &mut crossings) {
+                let col = crossing.colour;
+                let rat = col.w * self.disk.order_weight(crossing.order);
+                if rat > 0.0 {
+                    if hit == output::HIT_ESCAPED {
+                        hit = output::HIT_DISK;
+                        disk_radius = crossing.radius;
+                        disk_order = crossing.order as f32;
+                    }
+                    shift_sum += crossing.shift * alpha_rem * rat;
+                    shift_weight += alpha_rem * rat;
+                }
+                ccolor += col * alpha_rem * rat;
+                alpha_rem -= alpha_rem * rat;
+            }
+
+            pos = npos;
+            dir = ndir;
+
+            /* as in the aov shader module, with dir starting as a unit vector */
+            if self.drift && pos.dot(pos) > 1.0 {
+                let (h, null) = invariants(pos, dir);
+                drift_h = drift_h.max((h - h0).magnitude() / h0.magnitude().max(1.0));
+                drift_null = drift_null.max((null - null0).abs());
+            }
+
+            iterations += 1;
+        }
+
+        let colour = if self.drift {
+            let [r, g, b] = output::drift_colour(drift_h.max(drift_null));
+            vec4(r, g, b, 1.0)
+        } else {
+            ccolor + self.bg.colour(dir) * alpha_rem
+        };
+
+        let shift = if shift_weight > 0.0 { shift_sum / shift_weight } else { 0.0 };
+        let escape = if pos.dot(pos) > border { dir.normalize() } else { vec3(0.0, 0.0, 0.0) };
+        let aovs = [
+            hit,
+            iterations as f32,
+            escape.x,
+            escape.y,
+            escape.z,
+            disk_radius,
+            shift,
+            drift_h,
+            drift_null,
+            disk_order,
+        ];
+        (colour, aovs)
+    }
+
+    /// Returns the ray direction for a point in normalized device coordinates,
+    /// as computed by `ray_dir` in the shader.
+    pub fn ray_dir(&self, camera: &Camera, height_ratio: f32, x: f32, y: f32) -> Vector3<f32> {
+        let local = match self.projection {
+            Projection::Pinhole => {
+                let x = x * self.fov_ratio;
+                let y = y * self.fov_ratio * height_ratio;
+                vec3(x, y, 1.0)
+            }
+            Projection::Equirect => {
+                let yaw = x * f32::consts::PI;
+                let pitch = y * f32::consts::PI / 2.0;
+                vec3(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos())
+            }
+        };
+        camera.facing.transpose() * local
+    }
+
+    /// Returns the ray origin for an eye `offset` along the camera's right
+    /// vector, as computed by `ray_src` in the shader.
+    pub fn ray_src(&self, camera: &Camera, offset: f32, x: f32) -> Vector3<f32> {
+        let local = match self.projection {
+            Projection::Pinhole => vec3(offset, 0.0, 0.0),
+            Projection::Equirect => {
+                let yaw = x * f32::consts::PI;
+                vec3(yaw.cos(), 0.0, -yaw.sin()) * offset
+            }
+        };
+        camera.pos + camera.facing.transpose() * local
+    }
+
+    /// Renders a full image, splitting the rows between `threads` workers.
+    pub fn render(&self, dims: (u32, u32), camera: &Camera, time: f32, threads: usize) -> Frame {
+        let (width, height) = dims;
+        let eyes = self.stereo.eyes(self.ipd, dims);
+        let threads = threads.max(1);
+
+        let mut colours = vec![vec4(0.0, 0.0, 0.0, 0.0); (width * height) as usize];
+        let mut aovs = if self.aov { Some(Aovs::new(width, height)) } else { None };
+        let rows: Vec<Vec<(u32, Row)>> = thread::scope(|s| {
+            let workers: Vec<_> = (0..threads)
+                .map(|t| {
+                    let eyes = &eyes;
+                    s.spawn(move || {
+                        (t as u32..height)
+                            .step_by(threads)
+                            .map(|row| (row, self.render_row(camera, dims, eyes, row, time)))
+                            .collect()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        for (row, data) in rows.into_iter().flat_map(|r| r.into_iter()) {
+            let start = (row * width) as usize;
+            colours[start..start + width as usize].copy_from_slice(&data.colours);
+            if let Some(ref mut aovs) = aovs {
+                let n = output::CHANNELS.len();
+                for x in 0..width {
+                    let i = x as usize * n;
+                    aovs.pixel_mut(x, row).copy_from_slice(&data.aovs[i..i + n]);
+                }
+            }
+        }
+
+        let glare = self.glare.glare(&colours, &eyes, dims);
+        let image = image::RgbaImage::from_fn(width, height, |x, y| {
+            let i = (y * width + x) as usize;
+            let c = colours[i];
+            let mapped = self.tone_map.map(c.truncate().map(|x| x.max(0.0)) + glare[i]);
+            image::Rgba([
+                tex::to_srgb(mapped.x),
+                tex::to_srgb(mapped.y),
+                tex::to_srgb(mapped.z),
+                (c.w.clamp(0.0, 1.0) * 255.0).round() as u8,
+            ])
+        });
+        let hdr = if self.hdr {
+            Some(image::Rgba32FImage::from_fn(width, height, |x, y| {
+                let c = colours[(y * width + x) as usize];
+                image::Rgba([c.x, c.y, c.z, c.w])
+            }))
+        } else {
+            None
+        };
+        Frame {
+            image: image,
+            hdr: hdr,
+            aovs: aovs,
+        }
+    }
+
+    /// Traces a row of the image, rows starting at the top.
+    fn render_row(&self, camera: &Camera, dims: (u32, u32), eyes: &[Eye], row: u32, time: f32) -> Row {
+        let (width, height) = dims;
+        /* image rows start at the top, GL rows at the bottom */
+        let gl_row = height - 1 - row;
+
+        let mut data = Row {
+            colours: Vec::with_capacity(width as usize),
+            aovs: Vec::new(),
+        };
+        for col in 0..width {
+            /* the eye whose viewport holds the pixel, as on the GPU any odd
+             * pixel left between the views stays transparent */
+            let eye = eyes.iter().find(|e| {
+                let (left, bottom, w, h) = e.viewport;
+                col >= left && col < left + w && gl_row >= bottom && gl_row < bottom + h
+            });
+            let eye = match eye {
+                Some(eye) => eye,
+                None => {
+                    data.colours.push(vec4(0.0, 0.0, 0.0, 0.0));
+                    if self.aov {
+                        data.aovs.extend_from_slice(&[0.0; output::CHANNELS.len()]);
+                    }
+                    continue;
+                }
+            };
+            let (left, bottom, w, h) = eye.viewport;
+            let weight = 1.0 / self.subpixels.len() as f32;
+            let mut colour = vec4(0.0, 0.0, 0.0, 0.0);
+            for (i, &(dx, dy)) in self.subpixels.iter().enumerate() {
+                let x = 2.0 * ((col - left) as f32 + 0.5 + dx) / w as f32 - 1.0;
+                let y = 2.0 * ((gl_row - bottom) as f32 + 0.5 + dy) / h as f32 - 1.0;
+
+                let origin = self.ray_src(camera, eye.offset, x);
+                let dir = self.ray_dir(camera, h as f32 / w as f32, x, y);
+                let (c, aovs) = self.trace(camera.pos, origin, dir, time);
+                colour += c * weight;
+                /* the AOVs come from the first ray alone */
+                if self.aov && i == 0 {
+                    data.aovs.extend_from_slice(&aovs);
+                }
+            }
+            data.colours.push(colour);
+        }
+        data
+    }
+}
+
+fn yaw(v: Vector3<f32>) -> f32 {
+    v.x.atan2(v.z)
+}
+
+fn yaw_coord(v: Vector3<f32>) -> f32 {
+    (yaw(v) + f32::consts::PI) / (2. * f32::consts::PI)
+}
+
+fn pitch(v: Vector3<f32>) -> f32 {
+    /* GLSL leaves asin undefined outside [-1, 1], don't return NaN here */
+    v.y.clamp(-1.0, 1.0).asin()
+}
+
+fn pitch_coord(v: Vector3<f32>) -> f32 {
+    (pitch(v) + f32::consts::PI / 2.) / f32::consts::PI
+}
+
+/// The entry below `x` in a table spread evenly over `[0, 1]`, and how far
+/// `x` lies towards the next one.
+fn table_index(len: usize, x: f32) -> (usize, f32) {
+    let x = x.clamp(0.0, 1.0) * (len - 1) as f32;
+    let i = (x as usize).min(len - 2);
+    (i, x - i as f32)
+}
+
+fn lerp_table(table: &[f32], x: f32) -> f32 {
+    let (i, f) = table_index(table.len(), x);
+    table[i] + (table[i + 1] - table[i]) * f
+}
+
+fn lerp_colours(table: &[[f32; 3]], x: f32) -> Vector3<f32> {
+    let (i, f) = table_index(table.len(), x);
+    let (lo, hi) = (Vector3::from(table[i]), Vector3::from(table[i + 1]));
+    lo + (hi - lo) * f
+}
+
+/// GLSL's `mod`, which unlike `%` takes the sign of the divisor.
+fn glsl_mod(x: f32, y: f32) -> f32 {
+    x - y * (x / y).floor()
+}
+
+mod trace {
+    use cgmath::{vec3, InnerSpace, Vector3};
+
+    use std::f32::consts::PI;
+
+    use kerr;
+    use lut;
+    use scene::{Method, SceneConfig};
+
+    #[derive(Copy, Clone)]
+    pub enum Type {
+        Flat,
+        Verlet,
+        Rk4,
+        Kerr,
+        Rk45 {
+            tol: f32,
+            min_step: f32,
+            max_step: f32,
+        },
+        Lut(&'static lut::Table),
+    }
+
+    pub fn get_type(scene: &SceneConfig) -> Type {
+        if scene.flat {
+            Type::Flat
+        } else if scene.is_kerr() {
+            Type::Kerr
+        } else {
+            match scene.method {
+                Method::Flat => Type::Flat,
+                Method::Verlet => Type::Verlet,
+                Method::Rk4 => Type::Rk4,
+                Method::Rk45 => Type::Rk45 {
+                    tol: scene.tolerance,
+                    min_step: scene.min_step,
+                    max_step: scene.max_step,
+                },
+                Method::Lut => Type::Lut(lut::table()),
+            }
+        }
+    }
+
+    /// Per-ray state that the integrators carry between steps.
+    pub struct Vars {
+        pub h2: f32,
+        /// Covariant momentum, only used by the Kerr integrator.
+        pub p: Vector3<f32>,
+        /// Current step size, only used by the adaptive integrator.
+        pub time_step: f32,
+        /// Angular momentum about the spin axis per unit energy of the
+        /// photon arriving at the camera.
+        pub photon_l: f32,
+        /// Only used by the lookup table.
+        pub orbit: Option<Orbit>,
+    }
+
+    /// Where a ray is on its orbit in the lookup table, the `lut_` variables
+    /// of the shader.
+    pub struct Orbit {
+        table: &'static lut::Table,
+        /// The plane of the orbit, in which the ray turns from `e1` towards
+        /// `e2`.
+        e1: Vector3<f32>,
+        e2: Vector3<f32>,
+        /// The column of the orbit in the table.
+        x: f32,
+        /// The angle and `u` the incoming half of the orbit ends at.
+        phi_end: f32,
+        u_end: f32,
+        /// The angle into the incoming half the ray starts at.
+        phi_in: f32,
+        /// 1 if the ray moves along the incoming half, -1 if back out.
+        along: f32,
+        /// The angle turned when the ray escapes or falls in.
+        end: f32,
+        captured: bool,
+        /// The angle turned at the next crossing of the disk plane.
+        node: f32,
+    }
+
+    impl Orbit {
+        fn new(table: &'static lut::Table, pos: Vector3<f32>, dir: Vector3<f32>, h2: f32) -> Self {
+            let r = pos.magnitude();
+            let e1 = pos / r;
+            let mut e2 = dir - dir.dot(e1) * e1;
+            if e2.dot(e2) < 1e-12 {
+                /* a radial ray lies in any plane through it */
+                let axis = if e1.x.abs() < 0.9 { vec3(1.0, 0.0, 0.0) } else { vec3(0.0, 1.0, 0.0) };
+                e2 = e1.cross(axis);
+            }
+            let e2 = e2.normalize();
+
+            /* the impact parameter, from h and the null constraint */
+            let b = (h2 / (dir.dot(dir) - 2.0 * kerr::M * h2 / (r * r * r)).max(1e-12)).sqrt();
+            let x = lut::coord(b);
+            let col = table.sample(x, 0.0);
+            let u_end = if b < lut::B_CRIT { 1.0 / (2.0 * kerr::M) } else { col[3] / b };
+            let phi_in = table.sample(x, (1.0 / (r * u_end)).min(1.0))[1] * col[2];
+            let along = if pos.dot(dir) < 0.0 { 1.0 } else { -1.0 };
+
+            /* as in the shader, rays above the critical b inside the photon
+               sphere fall in at once */
+            let bound = b >= lut::B_CRIT && r < 3.0 * kerr::M;
+            let end = if bound {
+                0.0
+            } else if along < 0.0 {
+                phi_in
+            } else if b < lut::B_CRIT {
+                col[2] - phi_in
+            } else {
+                2.0 * col[2] - phi_in
+            };
+
+            let node = if e1.y.abs() + e2.y.abs() > 0.0 {
+                super::glsl_mod((-e1.y).atan2(e2.y), PI)
+            } else {
+                1e30
+            };
+            Orbit {
+                table: table,
+                e1: e1,
+                e2: e2,
+                x: x,
+                phi_end: col[2],
+                u_end: u_end,
+                phi_in: phi_in,
+                along: along,
+                end: end,
+                captured: bound || (along > 0.0 && b < lut::B_CRIT),
+                node: node,
+            }
+        }
+
+        /// The radius of the ray once it has turned by `d`.
+        fn radius(&self, d: f32) -> f32 {
+            let phi = self.phi_in + self.along * d;
+            /* the outgoing half mirrors the incoming one */
+            let phi = if phi > self.phi_end { 2.0 * self.phi_end - phi } else { phi };
+            let t = (phi / self.phi_end).clamp(0.0, 1.0);
+            1.0 / (self.table.sample(self.x, t)[0] * self.u_end)
+        }
+
+        /// Jumps to the next crossing of the disk plane inside `border` or
+        /// to the end of the orbit, moving `pos` to the start of a short step
+        /// across it, as the shader's update does.
+        fn step(
+            &mut self,
+            border: f32,
+            pos: &mut Vector3<f32>,
+            dir: Vector3<f32>,
+        ) -> (Vector3<f32>, Vector3<f32>) {
+            let at = |d: f32| d.cos() * self.e1 + d.sin() * self.e2;
+            let crossing = self.node < self.end;
+            let r = if crossing { self.radius(self.node) } else { 0.0 };
+            if crossing && r * r < border {
+                let p = r * at(self.node);
+                *pos = r * (p + vec3(0.0, 1e-3 * r, 0.0)).normalize();
+                self.node += PI;
+                (r * (p - vec3(0.0, 1e-3 * r, 0.0)).normalize(), dir)
+            } else if self.captured {
+                let p = 2.0 * kerr::M * at(self.end);
+                *pos = 1.001 * p;
+                (0.999 * p, dir)
+            } else {
+                let ndir = at(self.end);
+                *pos = border.sqrt() * ndir;
+                (2.0 * *pos, ndir)
+            }
+        }
+    }
+
+    pub fn accel(pos: Vector3<f32>, h2: f32) -> Vector3<f32> {
+        -pos * 1.5 * h2 * pos.dot(pos).powf(-2.5)
+    }
+
+    fn ts_func(ts: f32, _pos: Vector3<f32>) -> f32 {
+        ts
+    }
+
+    impl Type {
+        pub fn vars(self, a: f32, ts: f32, pos: Vector3<f32>, dir: Vector3<f32>) -> Vars {
+            let h = pos.cross(dir);
+            let p = match self {
+                Type::Kerr => kerr::init_momentum(a, pos, dir),
+                _ => vec3(0.0, 0.0, 0.0),
+            };
+            let time_step = match self {
+                Type::Rk45 { min_step, max_step, .. } => ts.clamp(min_step, max_step),
+                _ => ts,
+            };
+            let h2 = h.dot(h);
+            let photon_l = match self {
+                Type::Flat => -h.y,
+                Type::Kerr => pos.x * p.z - pos.z * p.x,
+                _ => -h.y / (1.0 - 2.0 * kerr::M * h2 / pos.magnitude().powi(3)).max(1e-6).sqrt(),
+            };
+            let orbit = match self {
+                Type::Lut(table) => Some(Orbit::new(table, pos, dir, h2)),
+                _ => None,
+            };
+            Vars {
+                h2: h2,
+                p: p,
+                time_step: time_step,
+                photon_l: photon_l,
+                orbit: orbit,
+            }
+        }
+
+        /// Advances a ray by one step, returning the new position and
+        /// direction.  The lookup table also moves `pos`, see `Orbit::step`.
+        pub fn update(
+            self,
+            vars: &mut Vars,
+            a: f32,
+            ts: f32,
+            border: f32,
+            pos: &mut Vector3<f32>,
+            dir: Vector3<f32>,
+        ) -> (Vector3<f32>, Vector3<f32>) {
+            if let Some(ref mut orbit) = vars.orbit {
+                return orbit.step(border, pos, dir);
+            }
+            let pos = *pos;
+            let time_step = ts_func(ts, pos);
+            let (npos, ndir) = match self {
+                Type::Flat => return (pos + dir * time_step, dir),
+                Type::Lut(_) => unreachable!("rays of the lookup table follow their orbit"),
+                Type::Verlet => {
+                    let npos = pos + dir * time_step;
+                    let ndir = dir + accel(pos, vars.h2) * time_step;
+                    (npos, ndir)
+                }
+                Type::Rk4 => rk4(pos, dir, vars.h2, time_step),
+                Type::Kerr => {
+                    let (npos, p, vel) = kerr_rk4(a, pos, vars.p, time_step);
+                    vars.p = p;
+                    return (npos, vel.normalize());
+                }
+                Type::Rk45 {
+                    tol,
+                    min_step,
+                    max_step,
+                } => {
+                    let factor = |err: f32| (0.9 * (tol / err.max(1e-10)).powf(0.2)).clamp(0.2, 5.0);
+
+                    let mut res = rk45_step(pos, dir, vars.h2, vars.time_step);
+                    for _ in 1..8 {
+                        if res.2 <= tol || vars.time_step <= min_step {
+                            break;
+                        }
+                        vars.time_step = (vars.time_step * factor(res.2)).max(min_step);
+                        res = rk45_step(pos, dir, vars.h2, vars.time_step);
+                    }
+                    /* the next step starts from the size this one suggests */
+                    vars.time_step = (vars.time_step * factor(res.2)).clamp(min_step, max_step);
+                    (res.0, res.1)
+                }
+            };
+
+            if ndir.dot(ndir) > 100.0 {
+                /* experimental renormalization */
+                let ndir = ndir.normalize();
+                let h = ndir.cross(npos);
+                vars.h2 = h.dot(h);
+                (npos, ndir)
+            } else {
+                (npos, ndir)
+            }
+        }
+    }
+
+    pub fn rk4(pos: Vector3<f32>, dir: Vector3<f32>, h2: f32, time_step: f32) -> (Vector3<f32>, Vector3<f32>) {
+        let x1 = pos;
+        let v1 = dir;
+        let a1 = accel(x1, h2);
+
+        let x2 = pos + 0.5 * v1 * time_step;
+        let v2 = dir + 0.5 * a1 * time_step;
+        let a2 = accel(x2, h2);
+
+        let x3 = pos + 0.5 * v2 * time_step;
+        let v3 = dir + 0.5 * a2 * time_step;
+        let a3 = accel(x3, h2);
+
+        let x4 = pos + v3 * time_step;
+        let v4 = dir + a3 * time_step;
+        let a4 = accel(x4, h2);
+
+        let npos = pos + (time_step / 6.0) * (v1 + 2.0 * v2 + 2.0 * v3 + v4);
+        let ndir = dir + (time_step / 6.0) * (a1 + 2.0 * a2 + 2.0 * a3 + a4);
+        (npos, ndir)
+    }
+
+    /// One Dormand-Prince step, returning the new position and direction
+    /// along with the error estimate.
+    pub fn rk45_step(
+        pos: Vector3<f32>,
+        dir: Vector3<f32>,
+        h2: f32,
+        ts: f32,
+    ) -> (Vector3<f32>, Vector3<f32>, f32) {
+        let x1 = dir;
+        let v1 = accel(pos, h2);
+
+        let x2 = dir + ts * (1.0 / 5.0) * v1;
+        let v2 = accel(pos + ts * (1.0 / 5.0) * x1, h2);
+
+        let x3 = dir + ts * ((3.0 / 40.0) * v1 + (9.0 / 40.0) * v2);
+        let v3 = accel(pos + ts * ((3.0 / 40.0) * x1 + (9.0 / 40.0) * x2), h2);
+
+        let x4 = dir + ts * ((44.0 / 45.0) * v1 - (56.0 / 15.0) * v2 + (32.0 / 9.0) * v3);
+        let v4 = accel(pos + ts * ((44.0 / 45.0) * x1 - (56.0 / 15.0) * x2 + (32.0 / 9.0) * x3), h2);
+
+        let x5 = dir
+            + ts * ((19372.0 / 6561.0) * v1 - (25360.0 / 2187.0) * v2 + (64448.0 / 6561.0) * v3
+                - (212.0 / 729.0) * v4);
+        let v5 = accel(
+            pos + ts * ((19372.0 / 6561.0) * x1 - (25360.0 / 2187.0) * x2 + (64448.0 / 6561.0) * x3
+                - (212.0 / 729.0) * x4),
+            h2,
+        );
+
+        let x6 = dir
+            + ts * ((9017.0 / 3168.0) * v1 - (355.0 / 33.0) * v2 + (46732.0 / 5247.0) * v3
+                + (49.0 / 176.0) * v4
+                - (5103.0 / 18656.0) * v5);
+        let v6 = accel(
+            pos + ts * ((9017.0 / 3168.0) * x1 - (355.0 / 33.0) * x2 + (46732.0 / 5247.0) * x3
+                + (49.0 / 176.0) * x4
+                - (5103.0 / 18656.0) * x5),
+            h2,
+        );
+
+        let npos = pos
+            + ts * ((35.0 / 384.0) * x1 + (500.0 / 1113.0) * x3 + (125.0 / 192.0) * x4
+                - (2187.0 / 6784.0) * x5
+                + (11.0 / 84.0) * x6);
+        let ndir = dir
+            + ts * ((35.0 / 384.0) * v1 + (500.0 / 1113.0) * v3 + (125.0 / 192.0) * v4
+                - (2187.0 / 6784.0) * v5
+                + (11.0 / 84.0) * v6);
+
+        let x7 = ndir;
+        let v7 = accel(npos, h2);
+
+        /* difference between the 5th and embedded 4th order solutions */
+        let ex = ts
+            * ((71.0 / 57600.0) * x1 - (71.0 / 16695.0) * x3 + (71.0 / 1920.0) * x4
+                - (17253.0 / 339200.0) * x5
+                + (22.0 / 525.0) * x6
+                - (1.0 / 40.0) * x7);
+        let ev = ts
+            * ((71.0 / 57600.0) * v1 - (71.0 / 16695.0) * v3 + (71.0 / 1920.0) * v4
+                - (17253.0 / 339200.0) * v5
+                + (22.0 / 525.0) * v6
+                - (1.0 / 40.0) * v7);
+
+        (npos, ndir, ex.magnitude().max(ev.magnitude()))
+    }
+
+    /// One RK4 step of `kerr::deriv`, returning the new position, the new
+    /// momentum and the averaged coordinate velocity over the step.
+    pub fn kerr_rk4(
+        a: f32,
+        pos: Vector3<f32>,
+        p: Vector3<f32>,
+        time_step: f32,
+    ) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let (k1x, k1p) = kerr::deriv(a, pos, p);
+        let (k2x, k2p) = kerr::deriv(a, pos + 0.5 * time_step * k1x, p + 0.5 * time_step * k1p);
+        let (k3x, k3p) = kerr::deriv(a, pos + 0.5 * time_step * k2x, p + 0.5 * time_step * k2p);
+        let (k4x, k4p) = kerr::deriv(a, pos + time_step * k3x, p + time_step * k3p);
+
+        let vel = k1x + 2.0 * k2x + 2.0 * k3x + k4x;
+        let npos = pos + (time_step / 6.0) * vel;
+        let np = p + (time_step / 6.0) * (k1p + 2.0 * k2p + 2.0 * k3p + k4p);
+        (npos, np, vel)
+    }
+}
+
+mod bh {
+    use cgmath::{vec4, InnerSpace, Vector3, Vector4};
+
+    use kerr;
+    use scene::{self, SceneConfig};
+
+    pub enum Surface {
+        Black,
+        Checkered,
+    }
+
+    pub fn get_surface(scene: &SceneConfig) -> Surface {
+        match scene.surface {
+            scene::Surface::Black => Surface::Black,
+            scene::Surface::Checkered => Surface::Checkered,
+        }
+    }
+
+    /// Returns the point of the segment closest to the hole if that point lies
+    /// inside the horizon.
+    pub fn check(a: f32, horizon: f32, pos: Vector3<f32>, npos: Vector3<f32>) -> Option<Vector3<f32>> {
+        let d = pos - npos;
+        let t = pos.dot(d) / d.dot(d);
+        let t = t.clamp(0.0, 1.0);
+        let closest = pos + t * (npos - pos);
+
+        if kerr::ks_r(a, closest) <= horizon {
+            Some(closest)
+        } else {
+            None
+        }
+    }
+
+    impl Surface {
+        pub fn colour(&self, closest: Vector3<f32>) -> Vector4<f32> {
+            use std::f32::consts::PI;
+            match *self {
+                Surface::Black => vec4(0.0, 0.0, 0.0, 1.0),
+                Surface::Checkered => {
+                    let yaw = closest.y.atan2(closest.x);
+                    let pitch = ((closest.x * closest.x + closest.y * closest.y).sqrt() / closest.z).atan();
+                    let b0 = (yaw * 180.0 / PI / 15.0) as i32;
+                    let b1 = (pitch * 180.0 / PI / 15.0) as i32;
+                    let red = (b0 + b1) % 2;
+                    vec4(red as f32, 0.0, 0.0, 1.0)
+                }
+            }
+        }
+    }
+}
+
+mod ad {
+    use cgmath::{vec3, vec4, InnerSpace, Vector3, Vector4};
+
+    use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+    use blackbody;
+    use error::Result;
+    use kerr;
+    use scene::{self, SceneConfig};
+
+    use super::tex::{Texture, Wrap};
+    use super::{glsl_mod, lerp_colours, lerp_table, resources};
+
+    enum Type {
+        NoDisk,
+        White,
+        Tex,
+        Dynamic,
+        Blackbody(blackbody::Disk),
+    }
+
+    /// A ray passing through the disk.
+    pub struct Crossing {
+        pub colour: Vector4<f32>,
+        /// Kerr-Schild radius of the crossing.
+        pub radius: f32,
+        /// Frequency shift of the light from there.
+        pub shift: f32,
+        /// Crossings of the disk plane before this one.
+        pub order: u32,
+    }
+
+    pub struct Disk {
+        kind: Type,
+        i_rad: f32,
+        o_rad: f32,
+        tex: Texture,
+        spin: f32,
+        shift_grav: bool,
+        shift_doppler: bool,
+        order_weights: Vec<f32>,
+    }
+
+    impl Disk {
+        pub fn new(scene: &SceneConfig) -> Result<Self> {
+            let kind = match scene.disk {
+                scene::Disk::None => Type::NoDisk,
+                scene::Disk::White => Type::White,
+                scene::Disk::Img => Type::Tex,
+                scene::Disk::Dyno => Type::Dynamic,
+                scene::Disk::Blackbody => Type::Blackbody(blackbody::Disk::new(scene)),
+            };
+            Ok(Disk {
+                kind: kind,
+                i_rad: scene.inner_radius(),
+                o_rad: scene.outer_radius,
+                tex: Texture::new(&resources::disk()?),
+                spin: kerr::spin_length(scene.spin()),
+                shift_grav: scene.redshift,
+                shift_doppler: scene.doppler,
+                order_weights: scene.order_weights.clone(),
+            })
+        }
+
+        /// Returns what the ray sees if the segment crosses the disk plane
+        /// within the disk, counting the crossings of the plane in
+        /// `crossings`.
+        pub fn check(
+            &self,
+            pos: Vector3<f32>,
+            npos: Vector3<f32>,
+            time: f32,
+            photon_l: f32,
+            src: Vector3<f32>,
+            crossings: &mut u32,
+        ) -> Option<Crossing> {
+            let t = -pos.y / (npos.y - pos.y);
+            if (0.0..=1.0).contains(&t) {
+                let order = *crossings;
+                *crossings += 1;
+                let p = pos + t * (npos - pos);
+                let mag = p.magnitude();
+                if mag >= self.i_rad && mag <= self.o_rad {
+                    let r = kerr::ks_r(self.spin, p);
+                    let g = self.shift(r, photon_l, src);
+                    return Some(Crossing {
+                        colour: self.colour(p, mag, time, g),
+                        radius: r,
+                        shift: g,
+                        order: order,
+                    });
+                }
+            }
+            None
+        }
+
+        /// Opacity of the disk at a crossing of its plane after `order`
+        /// earlier ones, see `SceneConfig::order_weights`.
+        pub fn order_weight(&self, order: u32) -> f32 {
+            let last = self.order_weights.len() - 1;
+            self.order_weights[(order as usize).min(last)]
+        }
+
+        fn shift(&self, r: f32, photon_l: f32, src: Vector3<f32>) -> f32 {
+            let (g_grav, g_dopp) = kerr::disk_shift(self.spin, r, photon_l, src);
+            let g_grav = if self.shift_grav { g_grav } else { 1.0 };
+            let g_dopp = if self.shift_doppler { g_dopp } else { 1.0 };
+            g_grav * g_dopp
+        }
+
+        fn colour(&self, intersect: Vector3<f32>, mag: f32, time: f32, g: f32) -> Vector4<f32> {
+            match self.kind {
+                Type::NoDisk => vec4(0.0, 0.0, 0.0, 0.0),
+                Type::White => shift_col(vec4(1.0, 1.0, 1.0, 1.0), g),
+                Type::Tex => {
+                    let x = super::yaw_coord(intersect);
+                    let y = (self.o_rad - mag) / (self.o_rad - self.i_rad);
+                    shift_col(self.tex.sample(x, y, Wrap::Clamp), g)
+                }
+                Type::Dynamic => {
+                    let phi = true_ang(super::yaw(intersect), mag, time);
+                    let x = phi / (2. * PI);
+                    let y = (self.o_rad - mag) / (self.o_rad - self.i_rad);
+                    let val = self.tex.sample(x, y, Wrap::Clamp).x * 10.0;
+
+                    /* val doubles as the temperature, so the shifted colour
+                     * comes from val * g while the brightness scales with g^4 */
+                    let t = val * g;
+                    let scale = val * g.powi(4) / t.max(1e-6);
+                    vec4(
+                        t * scale,
+                        (t * 2.0 - 1.0).clamp(0.0, 1.0) * scale,
+                        (t * 3.0 - 2.0).clamp(0.0, 1.0) * scale,
+                        (-mag / 2.0).exp() * val,
+                    )
+                }
+                Type::Blackbody(ref disk) => {
+                    /* the shifted spectrum of a blackbody is a blackbody at g
+                     * times its temperature */
+                    let x = (mag - self.i_rad) / (self.o_rad - self.i_rad);
+                    let t = lerp_table(&disk.temps, x) * g;
+                    if t <= 0.0 {
+                        return vec4(0.0, 0.0, 0.0, 1.0);
+                    }
+                    let x = (t.log10() - blackbody::LOG_T_MIN)
+                        / (blackbody::LOG_T_MAX - blackbody::LOG_T_MIN);
+                    lerp_colours(&disk.colours, x).extend(1.0)
+                }
+            }
+        }
+    }
+
+    /// Colour change of a 6500K blackbody seen shifted by `g`, with green
+    /// kept fixed.
+    fn bb_tint(g: f32) -> Vector3<f32> {
+        const LAMBDA: [f32; 3] = [610e-9, 550e-9, 465e-9];
+        const T0: f32 = 6500.0;
+        let g = g.max(0.1);
+        /* ratio of Planck's law at T0 * g and T0, hc / k = 0.014388 m K */
+        let t: Vec<f32> = LAMBDA
+            .iter()
+            .map(|l| ((0.014388 / (l * T0)).exp() - 1.0) / ((0.014388 / (l * T0 * g)).exp() - 1.0))
+            .collect();
+        vec3(t[0] / t[1], 1.0, t[2] / t[1])
+    }
+
+    /// Applies a frequency shift to a disk colour, moving its colour
+    /// temperature and scaling its intensity by `g^4`.
+    fn shift_col(col: Vector4<f32>, g: f32) -> Vector4<f32> {
+        let tint = bb_tint(g) * g.powi(4);
+        vec4(col.x * tint.x, col.y * tint.y, col.z * tint.z, col.w)
+    }
+
+    fn true_ang(phi_p: f32, mag: f32, time: f32) -> f32 {
+        let omega = FRAC_1_SQRT_2 * mag.powf(-1.5); /* keplerian vel */
+
+        glsl_mod(phi_p + omega * time * 5.0, 2.0 * PI)
+    }
+}
+
+mod bg {
+    use cgmath::{vec3, vec4, InnerSpace, Vector3, Vector4};
+
+    use error::Result;
+    use scene::{self, SceneConfig};
+    use stars::{self, Starfield};
+
+    use super::tex::{Cubemap, Texture, Wrap};
+    use super::{lerp_colours, resources};
+
+    pub enum Background {
+        Black,
+        Texture(Texture, f32),
+        Cube(Cubemap, f32),
+        Stars(Starfield, f32),
+    }
+
+    impl Background {
+        pub fn new(scene: &SceneConfig) -> Result<Self> {
+            Ok(match scene.bg {
+                scene::Background::Img => {
+                    let im = resources::background(scene.bg_image.as_deref())?;
+                    Background::Texture(Texture::linear(&im), scene.bg_ratio)
+                }
+                scene::Background::Cube => {
+                    let faces = resources::cubemap(scene.cube_image()?)?;
+                    Background::Cube(Cubemap::new(&faces), scene.bg_ratio)
+                }
+                scene::Background::Stars => Background::Stars(Starfield::new(scene), scene.bg_ratio),
+                scene::Background::Black => Background::Black,
+            })
+        }
+
+        pub fn colour(&self, dir: Vector3<f32>) -> Vector4<f32> {
+            match *self {
+                Background::Black => vec4(0.0, 0.0, 0.0, 1.0),
+                Background::Texture(ref tex, rat) => {
+                    let x = super::yaw_coord(dir);
+                    let y = super::pitch_coord(dir);
+
+                    let res = tex.sample(x, y, Wrap::Repeat);
+                    vec4(res.x * rat, res.y * rat, res.z * rat, res.w)
+                }
+                Background::Cube(ref cube, rat) => {
+                    let res = cube.sample(dir);
+                    vec4(res.x * rat, res.y * rat, res.z * rat, res.w)
+                }
+                Background::Stars(ref field, rat) => (starfield(field, dir) * rat).extend(1.0),
+            }
+        }
+    }
+
+    fn starfield(field: &Starfield, dir: Vector3<f32>) -> Vector3<f32> {
+        /* traced directions drift away from unit length */
+        let dir = dir.normalize();
+        let p = dir * stars::SHELL;
+        let c0 = vec3(p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+
+        /* stars close enough to show up lie in the cells around p */
+        let mut col = vec3(0.0, 0.0, 0.0);
+        for x in -1..2 {
+            for y in -1..2 {
+                for z in -1..2 {
+                    let cell = c0 + vec3(x, y, z);
+                    let mut h = stars::cell_hash(field.seed, cell);
+                    let present = stars::unit(&mut h);
+                    let px = cell.x as f32 + stars::unit(&mut h);
+                    let py = cell.y as f32 + stars::unit(&mut h);
+                    let pz = cell.z as f32 + stars::unit(&mut h);
+                    let pos = vec3(px, py, pz);
+
+                    let r = pos.magnitude();
+                    let star = pos / r;
+                    let density = stars::DENSITY * (1.0 + stars::GALAXY_STARS * band(field, star));
+                    if (r - stars::SHELL).abs() >= 0.5 || present > density {
+                        continue;
+                    }
+
+                    /* star counts grow as 10^(0.6 m), which puts brightness
+                     * at the -2/3 power of a uniform variable */
+                    let brightness = stars::MIN_BRIGHTNESS * stars::unit(&mut h).powf(-2.0 / 3.0);
+                    /* favour cool stars */
+                    let t = stars::unit(&mut h);
+                    let tint = lerp_colours(&field.colours, t * t);
+
+                    let d = (star - dir).magnitude() / field.size;
+                    col += tint * brightness * (-0.5 * d * d).exp();
+                }
+            }
+        }
+
+        if field.galaxy.is_some() {
+            let n = 0.65 * noise(field.seed, dir * 5.0) + 0.35 * noise(field.seed, dir * 13.0);
+            col += vec3(1.0, 0.9, 0.75) * (stars::GALAXY_GLOW * band(field, dir) * (0.3 + 0.7 * n));
+        }
+        col
+    }
+
+    /// How deep `dir` lies in the galactic band, 1 in its plane.
+    fn band(field: &Starfield, dir: Vector3<f32>) -> f32 {
+        match field.galaxy {
+            Some(normal) => {
+                let lat = dir.dot(normal).clamp(-1.0, 1.0).asin() / stars::GALAXY_WIDTH;
+                (-lat * lat).exp()
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Value noise between 0 and 1 over the star lattice.
+    fn noise(seed: u32, p: Vector3<f32>) -> f32 {
+        let c = vec3(p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+        let f = vec3(p.x - p.x.floor(), p.y - p.y.floor(), p.z - p.z.floor());
+        let f = vec3(
+            f.x * f.x * (3.0 - 2.0 * f.x),
+            f.y * f.y * (3.0 - 2.0 * f.y),
+            f.z * f.z * (3.0 - 2.0 * f.z),
+        );
+        let v = |x, y, z| (stars::cell_hash(seed, c + vec3(x, y, z)) >> 8) as f32 / 16777216.0;
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        let x00 = lerp(v(0, 0, 0), v(1, 0, 0), f.x);
+        let x10 = lerp(v(0, 1, 0), v(1, 1, 0), f.x);
+        let x01 = lerp(v(0, 0, 1), v(1, 0, 1), f.x);
+        let x11 = lerp(v(0, 1, 1), v(1, 1, 1), f.x);
+        lerp(lerp(x00, x10, f.y), lerp(x01, x11, f.y), f.z)
+    }
+}
+
+/// The tone mapping pass, `shaders::tone_shader`.
+mod tone {
+    use cgmath::Vector3;
+
+    use scene::{self, SceneConfig};
+
+    pub struct ToneMap {
+        kind: scene::ToneMap,
+        exposure: f32,
+    }
+
+    impl ToneMap {
+        pub fn new(scene: &SceneConfig) -> Self {
+            ToneMap {
+                kind: scene.tone_map,
+                exposure: 2.0f32.powf(scene.exposure),
+            }
+        }
+
+        /// Maps a linear colour into `[0, 1]`, once clamped.
+        pub fn map(&self, c: Vector3<f32>) -> Vector3<f32> {
+            let c = c.map(|x| x.max(0.0)) * self.exposure;
+            match self.kind {
+                scene::ToneMap::Clamp => c,
+                scene::ToneMap::Reinhard => c.map(|x| x / (1.0 + x)),
+                scene::ToneMap::Aces => c.map(|x| x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)),
+                /* with an exposure bias of 2, mapping 11.2 to white */
+                scene::ToneMap::Filmic => c.map(|x| hable(2.0 * x) / hable(11.2)),
+            }
+        }
+    }
+
+    fn hable(x: f32) -> f32 {
+        const A: f32 = 0.15;
+        const B: f32 = 0.50;
+        const C: f32 = 0.10;
+        const D: f32 = 0.20;
+        const E: f32 = 0.02;
+        const F: f32 = 0.30;
+        (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+    }
+}
+
+/// The blur for the glare, `shaders::bloom_shader`.
+mod glare {
+    use cgmath::{vec3, Vector3, Vector4};
+
+    use bloom::{self, Kernel};
+    use scene::{Eye, SceneConfig};
+
+    pub struct Glare {
+        strength: f32,
+        radius: f32,
+    }
+
+    impl Glare {
+        pub fn new(scene: &SceneConfig) -> Self {
+            Glare {
+                strength: scene.bloom,
+                radius: scene.bloom_radius,
+            }
+        }
+
+        /// The glare to add to each of `colours`, an image of size `dims`
+        /// with rows from the top.
+        pub fn glare(&self, colours: &[Vector4<f32>], eyes: &[Eye], dims: (u32, u32)) -> Vec<Vector3<f32>> {
+            let zero = vec3(0.0, 0.0, 0.0);
+            if self.strength == 0.0 {
+                return vec![zero; colours.len()];
+            }
+            let (width, height) = (dims.0 as i32, dims.1 as i32);
+            let bright: Vec<_> = colours
+                .iter()
+                .map(|c| Vector3::from(bloom::bright(c.truncate().into(), bloom::THRESHOLD)))
+                .collect();
+
+            let mut across = vec![zero; colours.len()];
+            let mut blurred = vec![zero; colours.len()];
+            for eye in eyes {
+                let (left, bottom, w, h) = eye.viewport;
+                let kernel = Kernel::new(self.radius, h);
+                /* the viewport in image rows, which start at the top */
+                let (x0, x1) = (left as i32, (left + w) as i32);
+                let (y0, y1) = (height - (bottom + h) as i32, height - bottom as i32);
+
+                let blur = |src: &[Vector3<f32>], dst: &mut [Vector3<f32>], axis: (i32, i32)| {
+                    for y in y0..y1 {
+                        for x in x0..x1 {
+                            let mut sum = zero;
+                            let mut weights = 0.0;
+                            for i in -kernel.reach..kernel.reach + 1 {
+                                let (qx, qy) = (x + i * axis.0, y + i * axis.1);
+                                if qx < x0 || qx >= x1 || qy < y0 || qy >= y1 {
+                                    continue;
+                                }
+                                let w = kernel.weight(i);
+                                sum += src[(qy * width + qx) as usize] * w;
+                                weights += w;
+                            }
+                            dst[(y * width + x) as usize] = sum / weights;
+                        }
+                    }
+                };
+                blur(&bright, &mut across, (1, 0));
+                blur(&across, &mut blurred, (0, 1));
+            }
+            blurred.iter().map(|&b| b * self.strength).collect()
+        }
+    }
+}
+
+/// Software versions of the texture sampling done by the GPU.
+mod tex {
+    use cgmath::{vec4, Vector3, Vector4};
+    use image;
+
+    use super::resources::from_srgb;
+
+    #[derive(Copy, Clone)]
+    pub enum Wrap {
+        Repeat,
+        Clamp,
+    }
+
+    /// A texture in linear colour, stored bottom row first like the GL
+    /// textures uploaded by `Renderer`.
+    pub struct Texture {
+        width: u32,
+        height: u32,
+        texels: Vec<Vector4<f32>>,
+    }
+
+    impl Texture {
+        /// Decodes an sRGB image.
+        pub fn new(im: &image::RgbaImage) -> Self {
+            Texture::from_fn(im.dimensions(), |x, y| {
+                let p = im.get_pixel(x, y).0;
+                let c = |c: u8| from_srgb(c as f32 / 255.0);
+                vec4(c(p[0]), c(p[1]), c(p[2]), p[3] as f32 / 255.0)
+            })
+        }
+
+        /// Takes an image that is already linear, like those from
+        /// `resources::background`.
+        pub fn linear(im: &image::Rgba32FImage) -> Self {
+            Texture::from_fn(im.dimensions(), |x, y| Vector4::from(im.get_pixel(x, y).0))
+        }
+
+        fn from_fn<F>((width, height): (u32, u32), texel: F) -> Self
+        where
+            F: Fn(u32, u32) -> Vector4<f32>,
+        {
+            let mut texels = Vec::with_capacity((width * height) as usize);
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    texels.push(texel(x, y));
+                }
+            }
+            Texture {
+                width: width,
+                height: height,
+                texels: texels,
+            }
+        }
+
+        /// Bilinearly samples the texture at normalized coordinates.
+        pub fn sample(&self, u: f32, v: f32, wrap: Wrap) -> Vector4<f32> {
+            let x = u * self.width as f32 - 0.5;
+            let y = v * self.height as f32 - 0.5;
+            let (x0, y0) = (x.floor(), y.floor());
+            let (fx, fy) = (x - x0, y - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+
+            let t00 = self.texel(x0, y0, wrap);
+            let t10 = self.texel(x0 + 1, y0, wrap);
+            let t01 = self.texel(x0, y0 + 1, wrap);
+            let t11 = self.texel(x0 + 1, y0 + 1, wrap);
+
+            (t00 * (1.0 - fx) + t10 * fx) * (1.0 - fy) + (t01 * (1.0 - fx) + t11 * fx) * fy
+        }
+
+        fn texel(&self, x: i64, y: i64, wrap: Wrap) -> Vector4<f32> {
+            let (w, h) = (self.width as i64, self.height as i64);
+            let (x, y) = match wrap {
+                Wrap::Repeat => (x.rem_euclid(w), y.rem_euclid(h)),
+                Wrap::Clamp => (x.max(0).min(w - 1), y.max(0).min(h - 1)),
+            };
+            self.texels[(y * w + x) as usize]
+        }
+    }
+
+    /// A cubemap, whose faces clamp at their edges like GL's without
+    /// seamless filtering.
+    pub struct Cubemap {
+        faces: Vec<Texture>,
+    }
+
+    impl Cubemap {
+        /// Takes the faces from `resources::cubemap`.
+        pub fn new(faces: &[image::Rgba32FImage]) -> Self {
+            Cubemap {
+                faces: faces.iter().map(Texture::linear).collect(),
+            }
+        }
+
+        /// Samples in the direction `dir`, picking the face and the
+        /// coordinates on it the way GL does.
+        pub fn sample(&self, dir: Vector3<f32>) -> Vector4<f32> {
+            let (ax, ay, az) = (dir.x.abs(), dir.y.abs(), dir.z.abs());
+            let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+                if dir.x > 0.0 {
+                    (0, -dir.z, -dir.y, ax)
+                } else {
+                    (1, dir.z, -dir.y, ax)
+                }
+            } else if ay >= az {
+                if dir.y > 0.0 {
+                    (2, dir.x, dir.z, ay)
+                } else {
+                    (3, dir.x, -dir.z, ay)
+                }
+            } else if dir.z > 0.0 {
+                (4, dir.x, -dir.y, az)
+            } else {
+                (5, -dir.x, -dir.y, az)
+            };
+            let s = (sc / ma + 1.0) / 2.0;
+            let t = (tc / ma + 1.0) / 2.0;
+            /* t runs down the face, but textures are stored bottom row first */
+            self.faces[face].sample(s, 1.0 - t, Wrap::Clamp)
+        }
+    }
+
+    /// Encodes a linear colour channel the way an sRGB framebuffer does.
+    pub fn to_srgb(c: f32) -> u8 {
+        let c = c.clamp(0.0, 1.0);
+        let s = if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (s * 255.0).round() as u8
+    }
+}
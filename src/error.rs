@@ -0,0 +1,67 @@
+//! Errors reported to the user, as opposed to bugs, which still panic.
+
+use std::error;
+use std::fmt;
+use std::result;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A flag or scene value that is malformed or out of range.
+    Arg(String),
+    /// A scene file that could not be read or parsed: `(path, reason)`.
+    Scene(String, String),
+    /// A camera path that could not be read, parsed or followed.
+    Path(String, String),
+    /// An image that could not be decoded or uploaded: `(name, reason)`.
+    Asset(String, String),
+    /// Failure creating the GL context.
+    Context(String),
+    /// A texture, buffer or framebuffer GL could not create: `(what, reason)`.
+    Resource(String, String),
+    /// A draw call or buffer swap that GL rejected.
+    Draw(String),
+    /// GLSL that failed to compile or link.
+    Shader(Vec<ShaderError>),
+    /// An image that could not be written: `(path, reason)`.
+    Output(String, String),
+}
+
+/// One line of a shader compiler log, traced back to the generator that
+/// produced the offending code where the log gives a location.
+#[derive(Debug)]
+pub struct ShaderError {
+    /// The generator module and the line within its output.
+    pub source: Option<(&'static str, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Arg(ref msg) => write!(f, "{}", msg),
+            Error::Scene(ref path, ref msg) => write!(f, "invalid scene {}: {}", path, msg),
+            Error::Path(ref path, ref msg) => write!(f, "invalid camera path {}: {}", path, msg),
+            Error::Asset(ref name, ref msg) => write!(f, "could not load {}: {}", name, msg),
+            Error::Context(ref msg) => write!(f, "could not create GL context: {}", msg),
+            Error::Resource(ref what, ref msg) => write!(f, "could not create {}: {}", what, msg),
+            Error::Draw(ref msg) => write!(f, "could not draw: {}", msg),
+            Error::Shader(ref errors) => {
+                write!(f, "shader compilation failed")?;
+                for e in errors {
+                    match e.source {
+                        Some((module, line)) => {
+                            write!(f, "\n  in {} (line {}): {}", module, line, e.message)?
+                        }
+                        None => write!(f, "\n  {}", e.message)?,
+                    }
+                }
+                Ok(())
+            }
+            Error::Output(ref path, ref msg) => write!(f, "could not write {}: {}", path, msg),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,128 @@
+//! A GL context with no window behind it, made through EGL's surfaceless
+//! platform so it works without an X11 or Wayland server.
+
+extern crate glium;
+extern crate khronos_egl as egl;
+
+use glium::backend::{Backend, Context};
+use glium::debug::DebugCallbackBehavior;
+use glium::SwapBuffersError;
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+
+/// `EGL_PLATFORM_SURFACELESS_MESA`, from `EGL_MESA_platform_surfaceless`.
+const PLATFORM_SURFACELESS: egl::Enum = 0x31DD;
+
+type Egl = egl::DynamicInstance<egl::EGL1_5>;
+
+struct Surfaceless {
+    egl: Egl,
+    display: egl::Display,
+    context: egl::Context,
+}
+
+/// Creates a desktop GL 3.3 core context that renders only into textures.
+/// libEGL is loaded at run time, so the binary still starts on machines
+/// without it.
+pub fn context() -> Result<Rc<Context>, String> {
+    let backend = Surfaceless::new()?;
+    unsafe { Context::new(backend, false, DebugCallbackBehavior::default()) }
+        .map_err(|e| format!("{:?}", e))
+}
+
+impl Surfaceless {
+    fn new() -> Result<Self, String> {
+        let egl = unsafe { Egl::load_required() }.map_err(|e| e.to_string())?;
+
+        // Drivers without the Mesa platform usually give a headless default
+        // display instead.
+        let display = unsafe {
+            egl.get_platform_display(
+                PLATFORM_SURFACELESS,
+                egl::DEFAULT_DISPLAY,
+                &[egl::ATTRIB_NONE],
+            )
+            .ok()
+            .or_else(|| egl.get_display(egl::DEFAULT_DISPLAY))
+        };
+        let display = display.ok_or_else(|| "no EGL display".to_string())?;
+        egl.initialize(display)
+            .map_err(|e| format!("eglInitialize: {}", e))?;
+
+        let context = Self::create(&egl, display);
+        if context.is_err() {
+            let _ = egl.terminate(display);
+        }
+        let context = context?;
+        Ok(Surfaceless {
+            egl: egl,
+            display: display,
+            context: context,
+        })
+    }
+
+    fn create(egl: &Egl, display: egl::Display) -> Result<egl::Context, String> {
+        egl.bind_api(egl::OPENGL_API)
+            .map_err(|e| format!("eglBindAPI: {}", e))?;
+
+        let attrs = [
+            egl::SURFACE_TYPE,
+            egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE,
+            egl::OPENGL_BIT,
+            egl::NONE,
+        ];
+        let config = egl
+            .choose_first_config(display, &attrs)
+            .map_err(|e| format!("eglChooseConfig: {}", e))?
+            .ok_or_else(|| "no EGL config supports desktop GL".to_string())?;
+
+        let attrs = [
+            egl::CONTEXT_MAJOR_VERSION,
+            3,
+            egl::CONTEXT_MINOR_VERSION,
+            3,
+            egl::CONTEXT_OPENGL_PROFILE_MASK,
+            egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            egl::NONE,
+        ];
+        egl.create_context(display, config, None, &attrs)
+            .map_err(|e| format!("eglCreateContext: {}", e))
+    }
+}
+
+unsafe impl Backend for Surfaceless {
+    fn swap_buffers(&self) -> Result<(), SwapBuffersError> {
+        Ok(())
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        self.egl
+            .get_proc_address(symbol)
+            .map_or(ptr::null(), |f| f as *const c_void)
+    }
+
+    /// There is no default framebuffer, everything is drawn into textures.
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        (1, 1)
+    }
+
+    fn is_current(&self) -> bool {
+        self.egl.get_current_context() == Some(self.context)
+    }
+
+    unsafe fn make_current(&self) {
+        self.egl
+            .make_current(self.display, None, None, Some(self.context))
+            .expect("failed to make the EGL context current");
+    }
+}
+
+impl Drop for Surfaceless {
+    fn drop(&mut self) {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.terminate(self.display);
+    }
+}
@@ -0,0 +1,126 @@
+//! Kerr metric helpers shared by the shader generator and the CPU tracer.
+//!
+//! Rotating holes are traced in Cartesian Kerr-Schild coordinates with the
+//! spin axis along `y`, so the accretion disk stays in the `y = 0` plane.
+//! The functions here have GLSL twins in `shaders::frag_shader::bh` and
+//! `shaders::frag_shader::trace`.
+
+extern crate cgmath;
+
+use cgmath::{vec3, InnerSpace, Vector3};
+
+/// Mass of the hole, matching `M` in the fragment shader preamble.
+pub const M: f32 = 0.5;
+
+/// The spin parameter `a` in units of length.
+pub fn spin_length(spin: f32) -> f32 {
+    spin * M
+}
+
+/// Radius of the outer event horizon, `M + sqrt(M^2 - a^2)`.
+pub fn horizon(spin: f32) -> f32 {
+    let a = spin_length(spin);
+    M + (M * M - a * a).sqrt()
+}
+
+/// Radius of the innermost stable circular orbit for a disk in the
+/// equatorial plane, rotating in the positive sense about `y`.  Negative
+/// spins give the (larger) retrograde orbit.
+pub fn isco(spin: f32) -> f32 {
+    let chi = spin.abs();
+    let z1 = 1.0 + (1.0 - chi * chi).cbrt() * ((1.0 + chi).cbrt() + (1.0 - chi).cbrt());
+    let z2 = (3.0 * chi * chi + z1 * z1).sqrt();
+    let root = ((3.0 - z1) * (3.0 + z1 + 2.0 * z2)).sqrt();
+    M * (3.0 + z2 - spin.signum() * root)
+}
+
+/// The Kerr-Schild radial coordinate, which reduces to `length(pos)` when
+/// `a = 0`.
+pub fn ks_r(a: f32, pos: Vector3<f32>) -> f32 {
+    let a2 = a * a;
+    let rho2 = pos.dot(pos) - a2;
+    (0.5 * (rho2 + (rho2 * rho2 + 4.0 * a2 * pos.y * pos.y).sqrt())).sqrt()
+}
+
+/// Hamilton's equations for a photon with energy 1, with the metric written
+/// as `g = eta + f l l`.  Returns `(dx/dl, dp/dl)` for the spatial position
+/// and covariant momentum.
+///
+/// Rays are followed backwards from the camera.  Reversing time in the Kerr
+/// metric flips its sense of rotation, so this traces future pointing rays
+/// around a hole of spin `-a`, which unlike past pointing rays cross the
+/// horizon smoothly in (ingoing) Kerr-Schild coordinates.
+pub fn deriv(a: f32, x: Vector3<f32>, p: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let a = -a;
+    let a2 = a * a;
+    let r = ks_r(a, x);
+    let r2 = r * r;
+    let sig = r2 * r2 + a2 * x.y * x.y;
+    let big_a = r2 + a2;
+    let l = vec3((r * x.x - a * x.z) / big_a, x.y / r, (r * x.z + a * x.x) / big_a);
+    let f = 2.0 * M * r2 * r / sig;
+    let q = 1.0 + l.dot(p);
+
+    let dx = p - f * q * l;
+
+    let dr = r * (r2 * x + vec3(0.0, a2 * x.y, 0.0)) / sig;
+    let df = 2.0 * M * r2 * ((3.0 * a2 * x.y * x.y - r2 * r2) * dr - vec3(0.0, 2.0 * a2 * r * x.y, 0.0))
+        / (sig * sig);
+    let n = r * (x.x * p.x + x.z * p.z) + a * (x.x * p.z - x.z * p.x);
+    let dn = dr * (x.x * p.x + x.z * p.z) + vec3(r * p.x + a * p.z, 0.0, r * p.z - a * p.x);
+    let dlp = dn / big_a - n * 2.0 * r * dr / (big_a * big_a) + vec3(0.0, p.y / r, 0.0)
+        - x.y * p.y * dr / r2;
+    let dp = 0.5 * q * q * df + f * q * dlp;
+
+    (dx, dp)
+}
+
+/// Finds the covariant momentum at `pos` whose coordinate velocity points
+/// along `dir`, scaled so its energy is 1.  As in `deriv` this is for the
+/// time reversed ray.
+pub fn init_momentum(a: f32, pos: Vector3<f32>, dir: Vector3<f32>) -> Vector3<f32> {
+    let a = -a;
+    let r = ks_r(a, pos);
+    let r2 = r * r;
+    let big_a = r2 + a * a;
+    let l = vec3(
+        (r * pos.x - a * pos.z) / big_a,
+        pos.y / r,
+        (r * pos.z + a * pos.x) / big_a,
+    );
+    let f = 2.0 * M * r2 * r / (r2 * r2 + a * a * pos.y * pos.y);
+    let s = l.dot(dir);
+
+    /* solve g(v, v) = 0 for the future pointing dt/dl */
+    let vt = (f * s + (1.0 - f + f * s * s).sqrt()) / (1.0 - f);
+    let energy = vt - f * (vt + s);
+    (dir + f * (vt + s) * l) / energy
+}
+
+/// The frequency ratio `nu_obs / nu_emit` for light reaching a static camera
+/// at `camera` from disk matter on a circular equatorial orbit at radius `r`,
+/// for a photon with angular momentum `l` about the spin axis per unit
+/// energy.
+///
+/// The result is split into `(gravitational, doppler)` factors, where the
+/// Doppler part is the shift seen by a zero angular momentum observer at
+/// `r` and the gravitational part is the shift between that observer and
+/// the camera.
+pub fn disk_shift(a: f32, r: f32, l: f32, camera: Vector3<f32>) -> (f32, f32) {
+    let sqm = M.sqrt();
+    let r15 = r.powf(1.5);
+    let omega = sqm / (r15 + a * sqm);
+    let ut = (r15 + a * sqm) / (r.powf(0.75) * (r15 - 3.0 * M * r.sqrt() + 2.0 * a * sqm).max(1e-6).sqrt());
+
+    let delta = r * r - 2.0 * M * r + a * a;
+    let big_a = (r * r + a * a) * (r * r + a * a) - a * a * delta;
+    let alpha = (delta * r * r / big_a).max(0.0).sqrt();
+    let zamo = 2.0 * M * a * r / big_a;
+
+    let r_o = ks_r(a, camera);
+    let f_o = 2.0 * M * r_o * r_o * r_o / (r_o * r_o * r_o * r_o + a * a * camera.y * camera.y);
+
+    let g_grav = alpha / ((1.0 - f_o).sqrt() * (1.0 - zamo * l));
+    let g_dopp = (1.0 - zamo * l) / (alpha * ut * (1.0 - omega * l));
+    (g_grav, g_dopp)
+}
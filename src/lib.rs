@@ -0,0 +1,64 @@
+//! A general relativity ray tracer: it draws black holes, their accretion
+//! disks and the sky behind them as light bent around the hole would show
+//! them.
+//!
+//! A `Scene` describes what to draw and how, a `Camera` where from, and a
+//! `Renderer` traces them on the GPU into a window or an offscreen `Frame`:
+//!
+//! ```no_run
+//! extern crate gr_trace;
+//!
+//! use gr_trace::{render, Camera, Renderer, Scene};
+//!
+//! # fn main() -> gr_trace::Result<()> {
+//! let scene = Scene {
+//!     width: 1920,
+//!     height: 1080,
+//!     ..Scene::default()
+//! };
+//!
+//! let display = render::headless()?;
+//! let renderer = Renderer::new(&display, &scene)?;
+//! let frame = renderer.render_image(&display, scene.dims(), &Camera::default(), scene.time)?;
+//! frame.save("out.png")
+//! # }
+//! ```
+//!
+//! `Tracer` renders the same images on the CPU, and `Shader` gives the GLSL
+//! generated for a scene to programs drawing it themselves.  The `gr_trace`
+//! binary is a command line front end to all of these.
+
+#[macro_use]
+pub extern crate glium;
+pub extern crate cgmath;
+extern crate image;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate time;
+extern crate toml;
+
+mod blackbody;
+mod bloom;
+mod camera;
+pub mod cpu;
+pub mod error;
+mod headless;
+mod kerr;
+mod lut;
+pub mod output;
+pub mod path;
+pub mod render;
+mod resources;
+pub mod scene;
+pub mod shaders;
+mod stars;
+
+pub use camera::Camera;
+pub use cpu::Tracer;
+pub use error::{Error, Result};
+pub use output::Frame;
+pub use path::CameraPath;
+pub use render::Renderer;
+pub use scene::SceneConfig as Scene;
+pub use shaders::Shader;
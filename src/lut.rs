@@ -0,0 +1,170 @@
+//! The deflection table of the `lut` method.
+//!
+//! Light around a Schwarzschild hole moves in a plane, along an orbit set by
+//! its impact parameter `b` alone.  With `u = 1 / r` and `phi` the angle it
+//! has turned, every orbit follows Binet's equation `u'' = 3M u^2 - u` from
+//! `u = 0` and `u' = 1 / b` far away.  Below the critical `b = 3√3 M` it
+//! falls into the horizon, above it it turns at a periapsis and leaves again
+//! along the mirror image of the way it came in.
+//!
+//! The table holds the incoming half of each of these orbits, up to the
+//! horizon or the periapsis, so that a ray can find where it is on its
+//! orbit, where that orbit crosses the disk and where it ends with a few
+//! lookups instead of integrating its path.  It is computed once and shared
+//! by the shader, as the `lut` texture, and the CPU tracer.
+
+use std::sync::OnceLock;
+
+use kerr;
+
+/// Number of impact parameters tabulated, half of them below the critical
+/// one.
+pub const WIDTH: usize = 1024;
+/// Number of points along each orbit.
+pub const HEIGHT: usize = 256;
+
+/// The critical impact parameter, which separates the orbits that fall in
+/// from those that escape.
+pub const B_CRIT: f32 = 3.0 * 1.732_050_8 * kerr::M;
+
+/// Step in `phi` the orbits are integrated with, for impact parameters of
+/// at least 1.  Narrower orbits, which turn through less, take smaller steps.
+const STEP: f64 = 1e-3;
+
+/// Column `i` holds the orbit of impact parameter `impact((i + 0.5) /
+/// WIDTH)`, and row `j` the fraction `t = j / (HEIGHT - 1)` along it.  The
+/// channels of each texel are:
+///
+/// - `u / u_end` at `phi = t phi_end`, where `u_end` is the `u` the orbit
+///   ends at.
+/// - The `t` at which `u / u_end` is `j / (HEIGHT - 1)`, as `u` grows along
+///   the incoming half.
+/// - `phi_end`, the angle the incoming half turns through.
+/// - `u_end b`, which stays near 1 for the wide orbits past the last column.
+pub struct Table {
+    /// Texels by rows, from `t = 0`.
+    pub data: Vec<[f32; 4]>,
+}
+
+/// The table, computed on first use.
+pub fn table() -> &'static Table {
+    static TABLE: OnceLock<Table> = OnceLock::new();
+    TABLE.get_or_init(Table::new)
+}
+
+/// The impact parameter at texture coordinate `x`.  The orbits that fall in
+/// fill the left half of the table and those that escape the right, spaced
+/// most closely near the critical one, where the deflection changes fastest.
+pub fn impact(x: f32) -> f32 {
+    if x < 0.5 {
+        let w = 1.0 - 2.0 * x;
+        B_CRIT * (1.0 - w * w)
+    } else {
+        let w = 2.0 * x - 1.0;
+        B_CRIT / (1.0 - w * w)
+    }
+}
+
+/// The texture coordinate of impact parameter `b`, kept within the half of
+/// the table with orbits of the same kind so that filtering doesn't mix the
+/// two.  This is `lut_coord` in the shader.
+pub fn coord(b: f32) -> f32 {
+    let edge = 0.5 / WIDTH as f32;
+    if b < B_CRIT {
+        let w = (1.0 - b / B_CRIT).sqrt();
+        ((1.0 - w) / 2.0).min(0.5 - edge)
+    } else {
+        let w = (1.0 - B_CRIT / b).sqrt();
+        ((1.0 + w) / 2.0).max(0.5 + edge)
+    }
+}
+
+impl Table {
+    fn new() -> Self {
+        let mut data = vec![[0.0; 4]; WIDTH * HEIGHT];
+        for i in 0..WIDTH {
+            let b = impact((i as f32 + 0.5) / WIDTH as f32) as f64;
+            let orbit = half_orbit(b);
+            let (phi_end, u_end) = orbit[orbit.len() - 1];
+            let inverse: Vec<_> = orbit.iter().map(|&(phi, u)| (u, phi)).collect();
+            for j in 0..HEIGHT {
+                let t = j as f64 / (HEIGHT - 1) as f64;
+                data[j * WIDTH + i] = [
+                    (interpolate(&orbit, t * phi_end) / u_end) as f32,
+                    (interpolate(&inverse, t * u_end) / phi_end) as f32,
+                    phi_end as f32,
+                    (u_end * b) as f32,
+                ];
+            }
+        }
+        Table { data: data }
+    }
+
+    /// Samples the table at `(x, t)`, interpolating between the texels as
+    /// `lut_at` in the shader does.
+    pub fn sample(&self, x: f32, t: f32) -> [f32; 4] {
+        let at = |c: f32, n: usize| {
+            let c = c.max(0.0).min((n - 1) as f32);
+            let i = (c as usize).min(n - 2);
+            (i, c - i as f32)
+        };
+        let (i, fx) = at(x * WIDTH as f32 - 0.5, WIDTH);
+        let (j, fy) = at(t * (HEIGHT - 1) as f32, HEIGHT);
+        let texel = |i: usize, j: usize| self.data[j * WIDTH + i];
+        let (a, b, c, d) = (texel(i, j), texel(i + 1, j), texel(i, j + 1), texel(i + 1, j + 1));
+        let mut out = [0.0; 4];
+        for k in 0..4 {
+            let lo = a[k] + (b[k] - a[k]) * fx;
+            let hi = c[k] + (d[k] - c[k]) * fx;
+            out[k] = lo + (hi - lo) * fy;
+        }
+        out
+    }
+}
+
+/// Integrates the incoming half of the orbit of impact parameter `b`,
+/// returning `(phi, u)` at even steps and where it ends.
+fn half_orbit(b: f64) -> Vec<(f64, f64)> {
+    let m = kerr::M as f64;
+    let accel = |u: f64| 3.0 * m * u * u - u;
+    let horizon = 1.0 / (2.0 * m);
+    let step = STEP * b.min(1.0);
+
+    let (mut phi, mut u, mut du) = (0.0, 0.0, 1.0 / b);
+    let mut orbit = vec![(phi, u)];
+    loop {
+        let (k1u, k1d) = (du, accel(u));
+        let (k2u, k2d) = (du + 0.5 * step * k1d, accel(u + 0.5 * step * k1u));
+        let (k3u, k3d) = (du + 0.5 * step * k2d, accel(u + 0.5 * step * k2u));
+        let (k4u, k4d) = (du + step * k3d, accel(u + step * k3u));
+        let nu = u + step / 6.0 * (k1u + 2.0 * k2u + 2.0 * k3u + k4u);
+        let ndu = du + step / 6.0 * (k1d + 2.0 * k2d + 2.0 * k3d + k4d);
+
+        if nu >= horizon {
+            let f = (horizon - u) / (nu - u);
+            orbit.push((phi + f * step, horizon));
+            return orbit;
+        }
+        if ndu <= 0.0 {
+            /* the periapsis, where u' changes sign */
+            let f = du / (du - ndu);
+            orbit.push((phi + f * step, u + (nu - u) * f));
+            return orbit;
+        }
+        phi += step;
+        u = nu;
+        du = ndu;
+        orbit.push((phi, u));
+    }
+}
+
+/// The second coordinate of the points at `x` in the first, which both grow
+/// along them, interpolated linearly.
+fn interpolate(points: &[(f64, f64)], x: f64) -> f64 {
+    let i = points.partition_point(|p| p.0 < x).max(1).min(points.len() - 1);
+    let (a, b) = (points[i - 1], points[i]);
+    if b.0 <= a.0 {
+        return b.1;
+    }
+    a.1 + (b.1 - a.1) * ((x - a.0) / (b.0 - a.0)).clamp(0.0, 1.0)
+}
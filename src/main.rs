@@ -1,8 +1,10 @@
-#[macro_use]
-extern crate glium;
-extern crate cgmath;
+//! The `gr_trace` command line: flies a camera around a scene in a window,
+//! or renders images and animations of it offscreen or on the CPU.
+
 extern crate clap;
-extern crate image;
+extern crate glium;
+extern crate gr_trace;
+extern crate serde;
 extern crate time;
 
 use glium::glutin::{EventsLoop, VirtualKeyCode, WindowBuilder};
@@ -12,13 +14,15 @@ use std::collections::HashSet;
 use time::precise_time_ns;
 
 use clap::{App, Arg, ArgMatches};
+use serde::de::{DeserializeOwned, IntoDeserializer};
 
-use cgmath::{vec3, Euler, Matrix3, Vector3};
+use std::fmt::Display;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+use std::str::FromStr;
 
-use std::fmt;
-
-mod render;
-mod shaders;
+use gr_trace::{render, Camera, CameraPath, Error, Frame, Renderer, Result, Scene, Tracer};
 
 #[allow(dead_code)]
 mod settings {
@@ -27,19 +31,37 @@ mod settings {
 }
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     let args = arg_handle();
+    let scene = scene(&args)?;
+    if let Some(file) = args.value_of("path") {
+        run_path(&args, &scene, file)
+    } else if args.is_present("cpu") {
+        run_cpu(&args, &scene)
+    } else if args.is_present("headless") {
+        run_headless(&args, &scene)
+    } else {
+        run_interactive(&scene)
+    }
+}
+
+fn run_interactive(scene: &Scene) -> Result<()> {
     let mut events_loop = EventsLoop::new();
-    let window = build_display();
+    let window = build_display(scene);
     let context = glium::glutin::ContextBuilder::new();
 
-    let display = glium::Display::new(window, context, &events_loop).unwrap();
+    let display =
+        glium::Display::new(window, context, &events_loop).map_err(|e| Error::Context(e.to_string()))?;
 
-    let renderer = render::Renderer::new(&display, &args);
+    let renderer = Renderer::new(&display, scene)?;
 
-    let mut camera = Camera {
-        pos: vec3(0.0, 0.0, -10.0f32),
-        facing: Matrix3::look_at(vec3(0., 0., 1.), vec3(0., 1., 0.)),
-    };
+    let mut camera = Camera::default();
 
     let start = precise_time_ns();
     let mut prev = precise_time_ns();
@@ -48,7 +70,11 @@ fn main() {
         use time::precise_time_ns;
 
         let time = (precise_time_ns() - start) as f32 / 1000000000.0f32;
-        renderer.render(display.draw(), &camera, time);
+        let mut target = display.draw();
+        /* a frame must be finished even if drawing it failed */
+        let drawn = renderer.render(&display, &mut target, &camera, time);
+        target.finish().map_err(|e| Error::Draw(format!("{:?}", e)))?;
+        drawn?;
         display.finish();
 
         let time = precise_time_ns();
@@ -87,76 +113,253 @@ fn main() {
             break;
         }
     }
+    Ok(())
 }
 
-pub struct Camera {
-    pos: Vector3<f32>,
-    facing: Matrix3<f32>,
+fn run_headless(args: &ArgMatches, scene: &Scene) -> Result<()> {
+    let display = render::headless()?;
+    let renderer = Renderer::new(&display, scene)?;
+
+    let camera = Camera::default();
+
+    let start = precise_time_ns();
+    let frame = renderer.render_image(&display, scene.dims(), &camera, scene.time)?;
+    let dt = (precise_time_ns() - start) as f32 / 1000000000.0f32;
+
+    let path = args.value_of("out").unwrap();
+    frame.save(path)?;
+    println!("rendered {} in {}ms", path, dt * 1000.0f32);
+    if scene.drift {
+        report_drift(&frame);
+    }
+    Ok(())
 }
 
-impl Camera {
-    fn update(&mut self, keys: &HashSet<VirtualKeyCode>, dt: f32) {
-        use cgmath::Rad ;
-        use cgmath::SquareMatrix;
-        use cgmath::Zero;
-
-        let ang = Rad::<f32>(1f32 * dt);
-        let mut dist = 0.2f32;
-
-        let mut vert = 0.0;
-        let mut hori = 0.0;
-        let mut depth = 0.0;
-        let mut yaw = Rad::zero();
-        let mut pitch = Rad::zero();
-        let mut roll = Rad::zero();
-        for &k in keys {
-            match k {
-                VirtualKeyCode::W => vert += 1.0,
-                VirtualKeyCode::S => vert -= 1.0,
-                VirtualKeyCode::A => hori -= 1.0,
-                VirtualKeyCode::D => hori += 1.0,
-                VirtualKeyCode::Q => depth -= 1.0,
-                VirtualKeyCode::E => depth += 1.0,
-                VirtualKeyCode::LShift => dist = 2f32,
-                VirtualKeyCode::I => pitch = pitch - ang,
-                VirtualKeyCode::K => pitch = pitch + ang,
-                VirtualKeyCode::J => yaw = yaw - ang,
-                VirtualKeyCode::L => yaw = yaw + ang,
-                VirtualKeyCode::U => roll = roll + ang,
-                VirtualKeyCode::O => roll = roll - ang,
-                _ => (),
-            }
-        }
+fn run_cpu(args: &ArgMatches, scene: &Scene) -> Result<()> {
+    let tracer = Tracer::new(scene)?;
 
-        let mov = self.facing.invert().unwrap() * vec3(hori, vert, depth);
-        let rot = Matrix3::from(Euler {x: pitch, y: yaw, z: roll});
+    let camera = Camera::default();
+    let threads = cpu_threads(args)?;
 
-        self.pos = self.pos + mov * dist * dt;
-        self.facing = (self.facing.invert().unwrap() * rot).invert().unwrap();
+    let start = precise_time_ns();
+    let frame = tracer.render(scene.dims(), &camera, scene.time, threads);
+    let dt = (precise_time_ns() - start) as f32 / 1000000000.0f32;
+
+    let path = args.value_of("out").unwrap();
+    frame.save(path)?;
+    println!("rendered {} on {} threads in {}ms", path, threads, dt * 1000.0f32);
+    if scene.drift {
+        report_drift(&frame);
     }
+    Ok(())
 }
 
-impl fmt::Display for Camera {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let pos = self.pos;
-        let fw = self.facing.z;
-        let up = self.facing.y;
-        write!(f, "pos: {:?} dir: {:?} up: {:?}", pos, fw, up)
+/// Renders every frame of a camera path offscreen, or with the CPU tracer
+/// if `--cpu` is given, and writes them as numbered images.
+fn run_path(args: &ArgMatches, scene: &Scene, file: &str) -> Result<()> {
+    let path = CameraPath::load(file, arg(args, "fps")?)?;
+    let frames = path.frames();
+    let digits = std::cmp::max(4, (frames - 1).to_string().len());
+    let out = args.value_of("out").unwrap();
+
+    enum Backend {
+        Gpu(Rc<glium::backend::Context>, Box<Renderer>),
+        Cpu(Box<Tracer>, usize),
+    }
+    let backend = if args.is_present("cpu") {
+        Backend::Cpu(Box::new(Tracer::new(scene)?), cpu_threads(args)?)
+    } else {
+        let display = render::headless()?;
+        let renderer = Renderer::new(&display, scene)?;
+        Backend::Gpu(display, Box::new(renderer))
+    };
+
+    for frame in 0..frames {
+        let (camera, time) = path.frame(frame);
+
+        let start = precise_time_ns();
+        let rendered = match backend {
+            Backend::Gpu(ref display, ref renderer) => {
+                renderer.render_image(display, scene.dims(), &camera, time)?
+            }
+            Backend::Cpu(ref tracer, threads) => tracer.render(scene.dims(), &camera, time, threads),
+        };
+        let dt = (precise_time_ns() - start) as f32 / 1000000000.0f32;
+
+        let name = frame_name(out, frame, digits);
+        rendered.save(&name)?;
+        println!("rendered {} ({}/{}) in {}ms", name, frame + 1, frames, dt * 1000.0f32);
+        if scene.drift {
+            report_drift(&rendered);
+        }
     }
+    Ok(())
+}
+
+/// Prints the mean drift of the rays of `frame` and the pixels that drifted
+/// most, see `output`.
+fn report_drift(frame: &Frame) {
+    let aovs = match frame.aovs {
+        Some(ref aovs) => aovs,
+        None => return,
+    };
+    let (width, height) = (frame.image.width(), frame.image.height());
+    let (mut h, mut null) = (0.0f64, 0.0f64);
+    for y in 0..height {
+        for x in 0..width {
+            let (dh, dnull) = aovs.drift(x, y);
+            h += dh as f64;
+            null += dnull as f64;
+        }
+    }
+    let n = (width * height) as f64;
+    println!("drift: mean h {:.3e}, mean null {:.3e}", h / n, null / n);
+    for (x, y) in aovs.worst_drift(10) {
+        let (dh, dnull) = aovs.drift(x, y);
+        println!("  pixel ({}, {}): h {:.3e}, null {:.3e}", x, y, dh, dnull);
+    }
+}
+
+/// The file frame `frame` of an animation is written to, numbering the
+/// output file name: `out.png` becomes `out0000.png`, `out0001.png`, ...
+fn frame_name(out: &str, frame: usize, digits: usize) -> String {
+    let out = Path::new(out);
+    let stem = out.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let ext = out.extension().and_then(|s| s.to_str()).unwrap_or("png");
+    let name = format!("{}{:0width$}.{}", stem, frame, ext, width = digits);
+    out.with_file_name(name).to_string_lossy().into_owned()
+}
+
+fn cpu_threads(args: &ArgMatches) -> Result<usize> {
+    Ok(match arg(args, "threads")?.unwrap() {
+        0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        n => n,
+    })
 }
 
-fn build_display() -> WindowBuilder {
+fn build_display(scene: &Scene) -> WindowBuilder {
+    let (width, height) = scene.dims();
     WindowBuilder::new()
-        .with_dimensions(glium::glutin::dpi::LogicalSize::new(1024.0, 768.0))
+        .with_dimensions(glium::glutin::dpi::LogicalSize::new(width as f64, height as f64))
         //.with_visibility(false)
         .with_title(format!("gr-trace"))
 }
 
+/// Loads the scene named by `--scene`, if any, and applies the other flags
+/// on top of it.
+fn scene(args: &ArgMatches) -> Result<Scene> {
+    let mut scene = match args.value_of("scene") {
+        Some(path) => Scene::load(path)?,
+        None => Scene::default(),
+    };
+
+    set(&mut scene.width, args, "width")?;
+    set(&mut scene.height, args, "height")?;
+    set_choice(&mut scene.projection, args, "projection");
+    set(&mut scene.fov, args, "fov")?;
+    set_choice(&mut scene.stereo, args, "stereo");
+    set(&mut scene.ipd, args, "ipd")?;
+    set(&mut scene.time, args, "time")?;
+    set(&mut scene.supersample, args, "supersample")?;
+    set(&mut scene.tile, args, "tile")?;
+    scene.aov |= args.is_present("aov");
+    scene.drift |= args.is_present("drift");
+    set_choice(&mut scene.tone_map, args, "tone-map");
+    set(&mut scene.exposure, args, "exposure")?;
+    scene.hdr |= args.is_present("hdr");
+    set(&mut scene.bloom, args, "bloom")?;
+    set(&mut scene.bloom_radius, args, "bloom-radius")?;
+
+    scene.flat |= args.is_present("flat");
+    set_choice(&mut scene.method, args, "method");
+    set(&mut scene.iter, args, "iter")?;
+    set(&mut scene.time_step, args, "timestep")?;
+    set(&mut scene.tolerance, args, "tol")?;
+    set(&mut scene.min_step, args, "min-step")?;
+    set(&mut scene.max_step, args, "max-step")?;
+    if let Some(spin) = arg(args, "spin")? {
+        scene.spin = Some(spin);
+    }
+    set_choice(&mut scene.surface, args, "surface");
+
+    set_choice(&mut scene.bg, args, "bg");
+    if let Some(path) = args.value_of("bg-image") {
+        scene.bg_image = Some(path.to_string());
+    }
+    set(&mut scene.bg_ratio, args, "bgratio")?;
+    set(&mut scene.seed, args, "seed")?;
+    scene.galaxy |= args.is_present("galaxy");
+
+    set_choice(&mut scene.disk, args, "disk");
+    if let Some(ir) = arg(args, "ir")? {
+        scene.inner_radius = Some(ir);
+    }
+    set(&mut scene.outer_radius, args, "or")?;
+    set(&mut scene.mass, args, "mass")?;
+    set(&mut scene.mdot, args, "mdot")?;
+    scene.redshift |= args.is_present("redshift");
+    scene.doppler |= args.is_present("doppler");
+    if let Some(v) = args.value_of("order-weights") {
+        scene.order_weights = v
+            .split(',')
+            .map(|w| {
+                w.trim()
+                    .parse()
+                    .map_err(|e| Error::Arg(format!("invalid value '{}' for --order-weights: {}", v, e)))
+            })
+            .collect::<Result<_>>()?;
+    }
+
+    scene.validate()?;
+    Ok(scene)
+}
+
+/// Parses the value of the flag `--name`, if it was given.
+fn arg<T>(args: &ArgMatches, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match args.value_of(name) {
+        Some(v) => v
+            .parse()
+            .map(Some)
+            .map_err(|e| Error::Arg(format!("invalid value '{}' for --{}: {}", v, name, e))),
+        None => Ok(None),
+    }
+}
+
+fn set<T>(field: &mut T, args: &ArgMatches, name: &str) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(v) = arg(args, name)? {
+        *field = v;
+    }
+    Ok(())
+}
+
+/// Like `set`, for the scene's enums, whose names clap has already checked.
+fn set_choice<T: DeserializeOwned>(field: &mut T, args: &ArgMatches, name: &str) {
+    if let Some(v) = args.value_of(name) {
+        let de: serde::de::value::StrDeserializer<serde::de::value::Error> = v.into_deserializer();
+        *field = T::deserialize(de).unwrap();
+    }
+}
+
 fn arg_handle<'a>() -> ArgMatches<'a> {
     App::new(settings::NAME)
         .version(settings::VERSION)
         .author("Sean Purcell <iburinoc@gmail.com>")
         .about("GPU General Relativity Ray Tracer")
+        .arg(
+            Arg::with_name("scene")
+                .long("scene")
+                .help("Loads a scene description from a TOML file, which other flags override")
+                .takes_value(true)
+                .value_name("FILE"),
+        )
         .arg(
             Arg::with_name("flat")
                 .short("f")
@@ -169,8 +372,7 @@ fn arg_handle<'a>() -> ArgMatches<'a> {
                 .long("iter")
                 .help("Sets the number of iterations to raytrace")
                 .takes_value(true)
-                .value_name("ITER_NUM")
-                .default_value("1000"),
+                .value_name("ITER_NUM"),
         )
         .arg(
             Arg::with_name("timestep")
@@ -178,8 +380,7 @@ fn arg_handle<'a>() -> ArgMatches<'a> {
                 .long("timestep")
                 .help("Sets the length of each time step (where c = 1)")
                 .takes_value(true)
-                .value_name("TIME_STEP")
-                .default_value("0.64"),
+                .value_name("TIME_STEP"),
         )
         .arg(
             Arg::with_name("method")
@@ -188,11 +389,33 @@ fn arg_handle<'a>() -> ArgMatches<'a> {
                 .help("Sets the integration method to use")
                 .takes_value(true)
                 .value_name("METHOD")
-                .default_value("rk4")
                 .possible_value("rk4")
+                .possible_value("rk45")
                 .possible_value("verlet")
+                .possible_value("lut")
                 .possible_value("flat"),
         )
+        .arg(
+            Arg::with_name("tol")
+                .long("tol")
+                .help("Sets the error tolerance per step of the rk45 method")
+                .takes_value(true)
+                .value_name("TOL"),
+        )
+        .arg(
+            Arg::with_name("min-step")
+                .long("min-step")
+                .help("Sets the smallest time step the rk45 method may take")
+                .takes_value(true)
+                .value_name("TIME_STEP"),
+        )
+        .arg(
+            Arg::with_name("max-step")
+                .long("max-step")
+                .help("Sets the largest time step the rk45 method may take")
+                .takes_value(true)
+                .value_name("TIME_STEP"),
+        )
         .arg(
             Arg::with_name("bg")
                 .short("b")
@@ -200,38 +423,83 @@ fn arg_handle<'a>() -> ArgMatches<'a> {
                 .help("Sets the type of background used")
                 .takes_value(true)
                 .value_name("TYPE")
-                .default_value("img")
                 .possible_value("black")
-                .possible_value("img"),
+                .possible_value("img")
+                .possible_value("cube")
+                .possible_value("stars"),
         )
         .arg(
-            Arg::with_name("accdisk")
+            Arg::with_name("bg-image")
+                .long("bg-image")
+                .help("Sets the sky image: equirectangular for img, a directory of faces or a cross for cube")
+                .takes_value(true)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("disk")
                 .short("d")
                 .long("disk")
                 .help("Sets the type of accretion disk used")
                 .takes_value(true)
                 .value_name("TYPE")
-                .default_value("dyno")
                 .possible_value("none")
                 .possible_value("white")
                 .possible_value("img")
-                .possible_value("dyno"),
+                .possible_value("dyno")
+                .possible_value("blackbody"),
+        )
+        .arg(
+            Arg::with_name("mass")
+                .long("mass")
+                .help("Sets the mass of the black hole in solar masses, for the blackbody disk")
+                .takes_value(true)
+                .value_name("MASS"),
+        )
+        .arg(
+            Arg::with_name("mdot")
+                .long("mdot")
+                .help("Sets the accretion rate as a fraction of the Eddington rate, for the blackbody disk")
+                .takes_value(true)
+                .value_name("RATE"),
         )
         .arg(
-            Arg::with_name("iradius")
+            Arg::with_name("ir")
                 .long("ir")
-                .help("Sets the inner radius of the accretion disk")
+                .help("Sets the inner radius of the accretion disk (defaults to the ISCO)")
                 .takes_value(true)
-                .value_name("RADIUS")
-                .default_value("3"),
+                .value_name("RADIUS"),
         )
         .arg(
-            Arg::with_name("oradius")
+            Arg::with_name("or")
                 .long("or")
                 .help("Sets the outer radius of the accretion disk")
                 .takes_value(true)
-                .value_name("RADIUS")
-                .default_value("15"),
+                .value_name("RADIUS"),
+        )
+        .arg(
+            Arg::with_name("spin")
+                .long("spin")
+                .help("Traces a rotating (Kerr) hole with spin a/M in [-1, 1], replacing --method")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .value_name("SPIN"),
+        )
+        .arg(
+            Arg::with_name("redshift")
+                .long("redshift")
+                .help("Applies gravitational redshift to the accretion disk"),
+        )
+        .arg(
+            Arg::with_name("doppler")
+                .long("doppler")
+                .help("Applies the Doppler shift and beaming of the orbiting accretion disk"),
+        )
+        .arg(
+            Arg::with_name("order-weights")
+                .long("order-weights")
+                .help("Sets the disk's opacity in each image order, e.g. 1,0 for the direct image alone")
+                .takes_value(true)
+                .value_name("WEIGHTS"),
         )
         .arg(
             Arg::with_name("surface")
@@ -240,25 +508,60 @@ fn arg_handle<'a>() -> ArgMatches<'a> {
                 .help("Sets the type of black hole surface used")
                 .takes_value(true)
                 .value_name("SURFACE")
-                .default_value("black")
                 .possible_value("black")
                 .possible_value("checkered"),
         )
+        .arg(
+            Arg::with_name("projection")
+                .long("projection")
+                .help("Sets the projection: a perspective view, or a 360 degree equirectangular panorama")
+                .takes_value(true)
+                .value_name("PROJECTION")
+                .possible_value("pinhole")
+                .possible_value("equirect"),
+        )
         .arg(
             Arg::with_name("fov")
                 .long("fov")
                 .help("Sets the horizontal field of view (in degrees)")
                 .takes_value(true)
-                .value_name("FOV")
-                .default_value("90"),
+                .value_name("FOV"),
         )
         .arg(
-            Arg::with_name("bgrat")
+            Arg::with_name("stereo")
+                .long("stereo")
+                .help("Renders a view for each eye, side by side or over-under, with the left eye first")
+                .takes_value(true)
+                .value_name("LAYOUT")
+                .possible_value("mono")
+                .possible_value("side-by-side")
+                .possible_value("over-under"),
+        )
+        .arg(
+            Arg::with_name("ipd")
+                .long("ipd")
+                .help("Sets the distance between the eyes of a stereo view")
+                .takes_value(true)
+                .value_name("IPD"),
+        )
+        .arg(
+            Arg::with_name("bgratio")
                 .long("bgratio")
                 .help("Sets the factor by which the background is dimmed")
                 .takes_value(true)
-                .value_name("FACTOR")
-                .default_value("0.5"),
+                .value_name("FACTOR"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("Sets the seed of the stars background")
+                .takes_value(true)
+                .value_name("SEED"),
+        )
+        .arg(
+            Arg::with_name("galaxy")
+                .long("galaxy")
+                .help("Adds a galactic band to the stars background"),
         )
         .arg(
             Arg::with_name("out")
@@ -269,14 +572,121 @@ fn arg_handle<'a>() -> ArgMatches<'a> {
                 .takes_value(true)
                 .default_value("out.png"),
         )
+        .arg(
+            Arg::with_name("aov")
+                .long("aov")
+                .help("Also writes auxiliary outputs, such as what each ray hit, next to the output file"),
+        )
+        .arg(
+            Arg::with_name("drift")
+                .long("drift")
+                .help("Draws how far rays drift from the quantities they conserve and reports the worst pixels"),
+        )
+        .arg(
+            Arg::with_name("hdr")
+                .long("hdr")
+                .help("Also writes the raw colours, before tone mapping, to an EXR next to the output file"),
+        )
+        .arg(
+            Arg::with_name("tone-map")
+                .long("tone-map")
+                .help("Sets how bright colours are brought into the displayable range")
+                .takes_value(true)
+                .value_name("OPERATOR")
+                .possible_value("clamp")
+                .possible_value("reinhard")
+                .possible_value("aces")
+                .possible_value("filmic"),
+        )
+        .arg(
+            Arg::with_name("exposure")
+                .long("exposure")
+                .help("Sets the exposure in stops, scaling colours by 2^EV before tone mapping")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .value_name("EV"),
+        )
+        .arg(
+            Arg::with_name("bloom")
+                .long("bloom")
+                .help("Adds glare of the given strength around colours brighter than white")
+                .takes_value(true)
+                .value_name("STRENGTH"),
+        )
+        .arg(
+            Arg::with_name("bloom-radius")
+                .long("bloom-radius")
+                .help("Sets the width of the glare as a fraction of the view height")
+                .takes_value(true)
+                .value_name("RADIUS"),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .help("Renders a single frame offscreen and writes it to the output file"),
+        )
+        .arg(
+            Arg::with_name("cpu")
+                .long("cpu")
+                .help("Renders a single frame with the CPU reference tracer and writes it to the output file"),
+        )
+        .arg(
+            Arg::with_name("path")
+                .long("path")
+                .help("Renders the camera path in FILE offscreen, numbering the output file for each frame")
+                .takes_value(true)
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::with_name("fps")
+                .long("fps")
+                .help("Overrides the frame rate of the camera path")
+                .takes_value(true)
+                .value_name("FPS"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .help("Sets the number of threads used by the CPU tracer (0 uses every core)")
+                .takes_value(true)
+                .value_name("THREADS")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .help("Sets the width of the rendered image")
+                .takes_value(true)
+                .value_name("PIXELS"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .long("height")
+                .help("Sets the height of the rendered image")
+                .takes_value(true)
+                .value_name("PIXELS"),
+        )
+        .arg(
+            Arg::with_name("time")
+                .long("time")
+                .help("Sets the simulation time of a headless or CPU render (in seconds)")
+                .takes_value(true)
+                .value_name("TIME"),
+        )
+        .arg(
+            Arg::with_name("supersample")
+                .long("supersample")
+                .help("Averages N x N rays per pixel in headless and CPU renders, smoothing edges")
+                .takes_value(true)
+                .value_name("N"),
+        )
+        .arg(
+            Arg::with_name("tile")
+                .long("tile")
+                .help("Renders headless images in tiles of at most SIZE x SIZE pixels, for very large images")
+                .takes_value(true)
+                .value_name("SIZE"),
+        )
         .get_matches()
 }
-/*
-#[allow(dead_code)]
-fn write_img(display: &GlutinFacade, path: &str) {
-    let image: glium::texture::RawImage2d<u8> = display.read_front_buffer();
-    let image = image::ImageBuffer::from_raw(image.width, image.height, image.data.into_owned()).unwrap();
-    let image = image::DynamicImage::ImageRgba8(image);
-    let mut output = std::fs::File::create(&Path::new(path)).unwrap();
-    image.save(&mut output, image::ImageFormat::PNG).unwrap();
-}*/
+
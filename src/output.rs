@@ -0,0 +1,186 @@
+//! Rendered frames and the files they are written to.
+//!
+//! Besides the tone mapped image, renders can write the colours traced,
+//! before exposure and tone mapping, to an EXR with `--hdr`.
+//!
+//! They can also produce auxiliary outputs (AOVs) with `--aov`: per-pixel
+//! data about each ray for analysing a scene rather than looking at it.
+//! These are written next to the image as a float32 NumPy array of shape
+//! `(height, width, channels)`, rows from the top, with the channels in
+//! `CHANNELS`.
+//!
+//! With `--drift`, renders measure how far each ray drifts from the
+//! quantities its geodesic conserves: the angular momentum `h = pos x dir`,
+//! and the null constraint `|dir|^2 - R_s h^2 / r^3` of the equation of
+//! motion the integrators solve.  The image becomes a heat map of the drift,
+//! and the AOVs hold its measurements.
+
+extern crate image;
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use error::{Error, Result};
+
+/// Names of the auxiliary channels, in the order they are stored:
+///
+/// - `hit`: what the ray hit, one of `HIT_ESCAPED`, `HIT_HORIZON` and
+///   `HIT_DISK`.
+/// - `iterations`: integration steps taken.
+/// - `escape_*`: unit direction the ray left the scene in, zero if it did
+///   not escape.
+/// - `disk_radius`: radius of the first visible disk crossing, zero if none.
+/// - `shift`: frequency shift `nu_obs / nu_emit` of the disk light, averaged
+///   over the crossings by how much each adds to the colour, zero if none.
+/// - `drift_h`: largest change in `h` along the ray, relative to `h` at the
+///   camera or to `R_s` if that is smaller.  Zero unless drift is measured.
+/// - `drift_null`: largest change in the null constraint along the ray,
+///   relative to `|dir|^2` at the camera.  Zero unless drift is measured.
+/// - `disk_order`: order of the image the first visible disk crossing is
+///   in, the number of times the ray crossed the disk plane before it, -1
+///   if none.
+pub const CHANNELS: [&'static str; 10] = [
+    "hit",
+    "iterations",
+    "escape_x",
+    "escape_y",
+    "escape_z",
+    "disk_radius",
+    "shift",
+    "drift_h",
+    "drift_null",
+    "disk_order",
+];
+
+/// The ray neither crossed the disk nor fell in.
+pub const HIT_ESCAPED: f32 = 0.0;
+/// The ray fell through the horizon.
+pub const HIT_HORIZON: f32 = 1.0;
+/// The ray crossed the disk, and was stopped by it or went on to escape.
+pub const HIT_DISK: f32 = 2.0;
+
+/// The smallest drift the heat map tells apart from none.
+pub const DRIFT_MIN: f32 = 1e-6;
+
+/// The heat map colour of a drift, rising on a log scale from black at
+/// `DRIFT_MIN` through blue and red to yellow at 1.  The `aov` shader module
+/// has a copy.
+pub fn drift_colour(drift: f32) -> [f32; 3] {
+    let t = ((drift.max(DRIFT_MIN) / DRIFT_MIN).ln() / (1.0 / DRIFT_MIN).ln()).min(1.0);
+    let clamp = |v: f32| v.clamp(0.0, 1.0);
+    let (r, g) = (clamp(3.0 * t - 1.0), clamp(3.0 * t - 2.0));
+    [r, g, clamp(3.0 * t) - r]
+}
+
+pub struct Frame {
+    pub image: image::RgbaImage,
+    /// Linear colours, rows from the top.
+    pub hdr: Option<image::Rgba32FImage>,
+    pub aovs: Option<Aovs>,
+}
+
+impl Frame {
+    /// Writes the image to `path`, and the HDR image and AOVs, if any, to
+    /// `hdr_path(path)` and `aov_path(path)`.
+    pub fn save(&self, path: &str) -> Result<()> {
+        self.image
+            .save(path)
+            .map_err(|e| Error::Output(path.to_string(), e.to_string()))?;
+        if let Some(ref hdr) = self.hdr {
+            let hdr_path = hdr_path(path);
+            hdr.save(&hdr_path).map_err(|e| Error::Output(hdr_path.clone(), e.to_string()))?;
+        }
+        if let Some(ref aovs) = self.aovs {
+            aovs.save(&aov_path(path))?;
+        }
+        Ok(())
+    }
+}
+
+/// The AOVs of a frame, `CHANNELS.len()` floats per pixel, rows from the top.
+pub struct Aovs {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f32>,
+}
+
+impl Aovs {
+    pub fn new(width: u32, height: u32) -> Self {
+        Aovs {
+            width: width,
+            height: height,
+            data: vec![0.0; (width * height) as usize * CHANNELS.len()],
+        }
+    }
+
+    /// The channels of the pixel at `(x, y)`.
+    pub fn pixel(&self, x: u32, y: u32) -> &[f32] {
+        let i = (y * self.width + x) as usize * CHANNELS.len();
+        &self.data[i..i + CHANNELS.len()]
+    }
+
+    /// The drift of `h` and of the null constraint of the ray of `(x, y)`.
+    pub fn drift(&self, x: u32, y: u32) -> (f32, f32) {
+        let p = self.pixel(x, y);
+        (p[7], p[8])
+    }
+
+    /// The `n` pixels whose rays drifted furthest from either invariant,
+    /// worst first.
+    pub fn worst_drift(&self, n: usize) -> Vec<(u32, u32)> {
+        let drift = |&(x, y): &(u32, u32)| {
+            let (h, null) = self.drift(x, y);
+            h.max(null)
+        };
+        let mut pixels: Vec<_> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .collect();
+        pixels.sort_by(|a, b| drift(b).partial_cmp(&drift(a)).unwrap_or(Ordering::Equal));
+        pixels.truncate(n);
+        pixels
+    }
+
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut [f32] {
+        let i = (y * self.width + x) as usize * CHANNELS.len();
+        &mut self.data[i..i + CHANNELS.len()]
+    }
+
+    /// Writes the AOVs in NumPy's `.npy` format.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let err = |e: ::std::io::Error| Error::Output(path.to_string(), e.to_string());
+
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+            self.height,
+            self.width,
+            CHANNELS.len()
+        );
+        /* the magic, version and length take 10 bytes, and the data should
+         * start on a 64 byte boundary after a newline */
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+
+        let mut out = BufWriter::new(File::create(path).map_err(err)?);
+        out.write_all(b"\x93NUMPY\x01\x00").map_err(err)?;
+        out.write_all(&(header.len() as u16).to_le_bytes()).map_err(err)?;
+        out.write_all(header.as_bytes()).map_err(err)?;
+        for v in &self.data {
+            out.write_all(&v.to_le_bytes()).map_err(err)?;
+        }
+        out.flush().map_err(err)
+    }
+}
+
+/// The HDR image of `out.png` is written to `out.hdr.exr`.
+pub fn hdr_path(path: &str) -> String {
+    Path::new(path).with_extension("hdr.exr").to_string_lossy().into_owned()
+}
+
+/// The AOVs of `out.png` are written to `out.aov.npy`.
+pub fn aov_path(path: &str) -> String {
+    Path::new(path).with_extension("aov.npy").to_string_lossy().into_owned()
+}
@@ -0,0 +1,144 @@
+//! Keyframed camera paths for rendering animations, loaded from TOML with
+//! `--path`.  See `paths/orbit.toml` for the format.
+//!
+//! Each keyframe gives the camera position, the point it looks at and its up
+//! direction at some time into the animation, along with the simulation
+//! time shown at that moment.  Between keyframes every quantity follows a
+//! cubic spline, so the camera moves without jerks at the keys.
+
+extern crate cgmath;
+extern crate toml;
+
+use cgmath::Vector3;
+
+use std::fs;
+use std::ops::{Add, Mul, Sub};
+
+use error::{Error, Result};
+
+use Camera;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CameraPath {
+    /// Frames rendered per second of animation.
+    #[serde(default = "default_fps")]
+    pub fps: f32,
+    #[serde(rename = "key")]
+    keys: Vec<Key>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Key {
+    /// Seconds into the animation.
+    at: f32,
+    pos: [f32; 3],
+    look_at: [f32; 3],
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+    /// Simulation time, which runs alongside the animation if not given.
+    time: Option<f32>,
+}
+
+fn default_fps() -> f32 {
+    30.0
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+impl CameraPath {
+    /// Loads a path, optionally replacing the frame rate it gives.
+    pub fn load(path: &str, fps: Option<f32>) -> Result<Self> {
+        let err = |msg: String| Error::Path(path.to_string(), msg);
+        let text = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        let mut cam: CameraPath = toml::from_str(&text).map_err(|e| err(e.to_string()))?;
+        cam.fps = fps.unwrap_or(cam.fps);
+
+        if cam.fps <= 0.0 {
+            return Err(err(format!("fps must be positive, got {}", cam.fps)));
+        }
+        if cam.keys.is_empty() {
+            return Err(err("no keyframes".to_string()));
+        }
+        for w in cam.keys.windows(2) {
+            if w[1].at <= w[0].at {
+                return Err(err(format!("keyframe at {} does not come after {}", w[1].at, w[0].at)));
+            }
+        }
+        for k in &cam.keys {
+            if k.pos == k.look_at {
+                return Err(err(format!("keyframe at {} looks at its own position", k.at)));
+            }
+        }
+        Ok(cam)
+    }
+
+    /// The number of frames in the animation, including both ends.
+    pub fn frames(&self) -> usize {
+        let length = self.keys[self.keys.len() - 1].at - self.keys[0].at;
+        /* allow for rounding when the length is a whole number of frames */
+        (length * self.fps + 1e-3).floor() as usize + 1
+    }
+
+    /// The camera and simulation time of a frame.
+    pub fn frame(&self, frame: usize) -> (Camera, f32) {
+        self.at(self.keys[0].at + frame as f32 / self.fps)
+    }
+
+    /// The camera and simulation time at `t` seconds into the animation.
+    pub fn at(&self, t: f32) -> (Camera, f32) {
+        let keys = &self.keys;
+        if keys.len() == 1 {
+            let k = &keys[0];
+            return camera(k.pos.into(), k.look_at.into(), k.up.into(), k.sim_time());
+        }
+
+        /* the segment containing t, clamped to the ends of the path */
+        let i = match keys.iter().rposition(|k| k.at <= t) {
+            Some(i) => i.min(keys.len() - 2),
+            None => 0,
+        };
+        let s = ((t - keys[i].at) / (keys[i + 1].at - keys[i].at)).clamp(0.0, 1.0);
+        camera(
+            spline(keys, i, s, |k| Vector3::from(k.pos)),
+            spline(keys, i, s, |k| Vector3::from(k.look_at)),
+            spline(keys, i, s, |k| Vector3::from(k.up)),
+            spline(keys, i, s, |k| k.sim_time()),
+        )
+    }
+}
+
+impl Key {
+    fn sim_time(&self) -> f32 {
+        self.time.unwrap_or(self.at)
+    }
+}
+
+fn camera(pos: Vector3<f32>, look_at: Vector3<f32>, up: Vector3<f32>, time: f32) -> (Camera, f32) {
+    (Camera::look_at(pos, look_at, up), time)
+}
+
+/// Cubic Hermite interpolation between keys `i` and `i + 1`, with
+/// Catmull-Rom tangents scaled for the uneven spacing of the keys.
+fn spline<T, F>(keys: &[Key], i: usize, s: f32, get: F) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+    F: Fn(&Key) -> T,
+{
+    let tangent = |j: usize| {
+        let prev = &keys[j.saturating_sub(1)];
+        let next = &keys[(j + 1).min(keys.len() - 1)];
+        (get(next) - get(prev)) * (1.0 / (next.at - prev.at))
+    };
+    let dt = keys[i + 1].at - keys[i].at;
+    let (m0, m1) = (tangent(i) * dt, tangent(i + 1) * dt);
+
+    let (s2, s3) = (s * s, s * s * s);
+    get(&keys[i]) * (2.0 * s3 - 3.0 * s2 + 1.0)
+        + m0 * (s3 - 2.0 * s2 + s)
+        + get(&keys[i + 1]) * (3.0 * s2 - 2.0 * s3)
+        + m1 * (s3 - s2)
+}
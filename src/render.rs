@@ -1,14 +1,26 @@
 extern crate cgmath;
-extern crate clap;
 extern crate glium;
 extern crate image;
 extern crate time;
 
-use clap::ArgMatches;
 use glium::backend::Facade;
-use glium::{Frame, Surface};
+use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer, ToColorAttachment};
+use glium::texture::{ClientFormat, Cubemap, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{SamplerWrapFunction, Uniforms};
+use glium::Surface;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::f32;
+use std::fmt;
+use std::rc::Rc;
 
+use bloom;
+use error::{Error, Result};
+use headless;
+use lut;
+use output::{Aovs, Frame};
+use resources;
+use scene::{Background, Eye, Method, SceneConfig, Stereo};
 use shaders::Shader;
 
 use Camera;
@@ -16,79 +28,148 @@ use Camera;
 struct RenderParams {
     iter: i32,
     time_step: f32,
+    stereo: Stereo,
+    ipd: f32,
+    aov: bool,
+    hdr: bool,
+    bloom_radius: f32,
+    /// See `SceneConfig::subpixels`.
+    subpixels: Vec<(f32, f32)>,
+    tile: u32,
 }
 
 struct RenderBuffers(glium::VertexBuffer<RayVertex>, glium::IndexBuffer<u8>);
+/// The background texture, in whichever form the generated `bg_col` samples.
+enum Sky {
+    None,
+    Equirect(Texture2d),
+    Cube(Cubemap),
+}
+
 pub struct Renderer {
     program: glium::Program,
-    background: glium::texture::SrgbTexture2d,
+    tone_program: glium::Program,
+    /// The blur for the glare, if there is any.
+    bloom_program: Option<glium::Program>,
+    background: Sky,
     disk: glium::texture::SrgbTexture2d,
+    /// The orbit table of the `lut` method, or a single texel for the other
+    /// methods, which don't read it.
+    lut: Texture2d,
 
     buffers: RenderBuffers,
+    /// The images of the last frame drawn, kept for the next if it has the
+    /// same size.
+    targets: RefCell<Option<Targets>>,
 
     params: RenderParams,
 }
 
 impl Renderer {
-    pub fn new<F>(display: &F, args: &ArgMatches) -> Self
+    /// Compiles the shaders and uploads the textures for `scene`, failing if
+    /// it doesn't validate.
+    pub fn new<F>(display: &F, scene: &SceneConfig) -> Result<Self>
     where
         F: Facade,
     {
-        let bg = {
-            use std::io::Cursor;
-            //FIXME: insert alternate bg images here
-
-            let bytes = if cfg!(debug_assertions) {
-                &include_bytes!("../resources/bg-small.jpg")[..]
-            } else {
-                &include_bytes!("../resources/bg.jpg")[..]
-            };
-            let im = image::load(Cursor::new(bytes), image::JPEG)
-                .unwrap()
-                .to_rgba();
-
-            let imdim = im.dimensions();
-            let im = glium::texture::RawImage2d::from_raw_rgba_reversed(im.into_raw().as_slice(), imdim);
-            glium::texture::SrgbTexture2d::new(display, im).unwrap()
+        scene.validate()?;
+        let bg = match scene.bg {
+            /* the stars are generated in the shader */
+            Background::Black | Background::Stars => Sky::None,
+            Background::Img => Sky::Equirect(equirect(display, scene.bg_image.as_deref())?),
+            Background::Cube => Sky::Cube(cubemap(display, scene.cube_image()?)?),
         };
 
         let ad = {
-            use std::io::Cursor;
-
-            let bytes = &include_bytes!("../resources/ad.png")[..];
-            let im = image::load(Cursor::new(bytes), image::PNG)
-                .unwrap()
-                .to_rgba();
-
+            let im = resources::disk()?;
             let imdim = im.dimensions();
             let im = glium::texture::RawImage2d::from_raw_rgba_reversed(im.into_raw().as_slice(), imdim);
-            glium::texture::SrgbTexture2d::new(display, im).unwrap()
+            glium::texture::SrgbTexture2d::new(display, im)
+                .map_err(|e| Error::Asset("accretion disk texture".to_string(), format!("{:?}", e)))?
         };
 
-        let prog = Shader::construct(args).compile(display);
+        let prog = Shader::construct(scene).compile(display)?;
+        let tone_prog = Shader::tone_map(scene).compile(display)?;
+        let bloom_prog = if scene.bloom > 0.0 {
+            Some(Shader::bloom(scene).compile(display)?)
+        } else {
+            None
+        };
 
         let bufs = {
             use glium::index::PrimitiveType::TrianglesList;
             RenderBuffers(
-                glium::VertexBuffer::new(display, &VERTICES).unwrap(),
-                glium::IndexBuffer::new(display, TrianglesList, &INDICES).unwrap(),
+                glium::VertexBuffer::new(display, &VERTICES).map_err(gl_object("vertex buffer"))?,
+                glium::IndexBuffer::new(display, TrianglesList, &INDICES).map_err(gl_object("index buffer"))?,
             )
         };
 
-        Renderer {
+        Ok(Renderer {
             program: prog,
+            tone_program: tone_prog,
+            bloom_program: bloom_prog,
             background: bg,
             disk: ad,
+            lut: lut_texture(display, scene)?,
             buffers: bufs,
-            params: RenderParams::new(args),
-        }
+            targets: RefCell::new(None),
+            params: RenderParams::new(scene),
+        })
     }
 
-    pub fn render(&self, mut target: Frame, camera: &Camera, time: f32) {
-        target.clear_color(0., 0., 0., 0.0);
+    /// Renders a frame into `target`, tracing into a float image first and
+    /// post-processing that.
+    pub fn render<F, S>(&self, display: &F, target: &mut S, camera: &Camera, time: f32) -> Result<()>
+    where
+        F: Facade,
+        S: Surface,
+    {
+        let dims = target.get_dimensions();
+        let mut targets = self.targets.borrow_mut();
+        if targets.as_ref().map(|t| t.hdr.dimensions()) != Some(dims) {
+            *targets = Some(self.targets(display, hdr_texture(display, dims)?)?);
+        }
+        let targets = targets.as_ref().unwrap();
+        let tile = Tile::whole(dims);
 
-        let (width, height) = target.get_dimensions();
+        {
+            let mut traced =
+                SimpleFrameBuffer::new(display, &targets.hdr).map_err(gl_object("float framebuffer"))?;
+            traced.clear_color(0.0, 0.0, 0.0, 0.0);
+            self.trace(&mut traced, camera, time, tile, (0.0, 0.0), 1.0)?;
+        }
+        self.post(display, target, targets, tile)
+    }
 
+    /// Adds the images post-processing `hdr` needs.
+    fn targets<F: Facade>(&self, display: &F, hdr: Texture2d) -> Result<Targets> {
+        let dims = hdr.dimensions();
+        let bloom = if self.bloom_program.is_some() {
+            Some((hdr_texture(display, dims)?, hdr_texture(display, dims)?))
+        } else {
+            None
+        };
+        Ok(Targets {
+            hdr: hdr,
+            bloom: bloom,
+        })
+    }
+
+    /// Traces the part `tile` of the image into `target`, which should hold
+    /// float colours.  The rays pass `subpixel` pixels from the centres of
+    /// the pixels, and add `weight` of their colours to them.
+    fn trace<S>(
+        &self,
+        target: &mut S,
+        camera: &Camera,
+        time: f32,
+        tile: Tile,
+        subpixel: (f32, f32),
+        weight: f32,
+    ) -> Result<()>
+    where
+        S: Surface,
+    {
         let (src, facing_mat) = {
             use cgmath::Matrix;
 
@@ -98,28 +179,68 @@ impl Renderer {
             (src, facing_mat)
         };
 
-        let uniforms = uniform! {
-            height_ratio: (height as f32) / (width as f32),
-            fov_ratio: (f32::consts::PI * 2. / 3. / 2.).tan(), // pi/2, 90 deg
-            src: src,
-            facing: facing_mat,
-            bg_tex: self.background
-                .sampled().wrap_function(glium::uniforms::SamplerWrapFunction::Repeat),
-            ad_tex: self.disk
-                .sampled().wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)
-                .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
-                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear),
-            NUM_ITER: self.params.iter,
-            TIME_STEP: self.params.time_step,
-            time: time,
-        };
+        /* each eye draws the full-screen quad into the part of its view in
+         * the tile, moving the corners of the quad to the corners of that
+         * part */
+        for (eye, viewport) in tile.eyes(self.params.stereo, self.params.ipd) {
+            let (left, bottom, width, height) = eye.viewport;
+            let (x, y) = (tile.rect.left + viewport.left, tile.rect.bottom + viewport.bottom);
+            let (w, h) = (width as f32, height as f32);
+
+            let uniforms = uniform! {
+                height_ratio: h / w,
+                fov_ratio: (f32::consts::PI * 2. / 3. / 2.).tan(), // pi/2, 90 deg
+                src: src,
+                facing: facing_mat,
+                eye: eye.offset,
+                pos_scale: [viewport.width as f32 / w, viewport.height as f32 / h],
+                pos_offset: [
+                    (2 * (x - left) + viewport.width) as f32 / w - 1.0 + 2.0 * subpixel.0 / w,
+                    (2 * (y - bottom) + viewport.height) as f32 / h - 1.0 + 2.0 * subpixel.1 / h,
+                ],
+                weight: weight,
+                ad_tex: self.disk
+                    .sampled().wrap_function(SamplerWrapFunction::Clamp)
+                    .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                    .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear),
+                lut: self.lut
+                    .sampled().wrap_function(SamplerWrapFunction::Clamp)
+                    .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                    .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear),
+                NUM_ITER: self.params.iter,
+                TIME_STEP: self.params.time_step,
+                time: time,
+            };
 
+            /* each kind of sky is bound under the name its bg_col uses */
+            match self.background {
+                Sky::None => self.draw(target, viewport, &uniforms)?,
+                Sky::Equirect(ref tex) => {
+                    let bg_tex = tex.sampled().wrap_function(SamplerWrapFunction::Repeat);
+                    self.draw(target, viewport, &uniforms.add("bg_tex", bg_tex))?
+                }
+                Sky::Cube(ref tex) => {
+                    let bg_cube = tex.sampled().wrap_function(SamplerWrapFunction::Clamp);
+                    self.draw(target, viewport, &uniforms.add("bg_cube", bg_cube))?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw<S, U>(&self, target: &mut S, viewport: glium::Rect, uniforms: &U) -> Result<()>
+    where
+        S: Surface,
+        U: Uniforms,
+    {
         let params = glium::DrawParameters {
+            /* samples of a pixel add up */
             blend: glium::Blend {
-                color: glium::BlendingFunction::AlwaysReplace,
-                alpha: glium::BlendingFunction::AlwaysReplace,
+                color: ADD,
+                alpha: ADD,
                 constant_value: (0.0, 0.0, 0.0, 0.0),
             },
+            viewport: Some(viewport),
             ..Default::default()
         };
 
@@ -128,20 +249,540 @@ impl Renderer {
                 &self.buffers.0,
                 &self.buffers.1,
                 &self.program,
-                &uniforms,
+                uniforms,
                 &params,
             )
-            .unwrap();
+            .map_err(|e| Error::Draw(format!("{:?}", e)))
+    }
+
+    /// Adds the glare to the traced colours of the part `tile` of the image
+    /// and brings them into the range of `target`.
+    fn post<F, S>(&self, display: &F, target: &mut S, targets: &Targets, tile: Tile) -> Result<()>
+    where
+        F: Facade,
+        S: Surface,
+    {
+        let uniforms = uniform! {
+            hdr: nearest(&targets.hdr),
+        };
+        match targets.bloom {
+            Some((ref across, ref blurred)) => {
+                self.bloom(display, tile, &targets.hdr, across, blurred)?;
+                self.post_draw(target, &self.tone_program, &uniforms.add("bloom", nearest(blurred)), None)
+            }
+            None => self.post_draw(target, &self.tone_program, &uniforms, None),
+        }
+    }
+
+    /// Blurs the highlights of `hdr`, holding `tile`, into `across` along x,
+    /// then into `blurred` along y, see `bloom`.
+    fn bloom<F>(
+        &self,
+        display: &F,
+        tile: Tile,
+        hdr: &Texture2d,
+        across: &Texture2d,
+        blurred: &Texture2d,
+    ) -> Result<()>
+    where
+        F: Facade,
+    {
+        let program = self.bloom_program.as_ref().unwrap();
+        let eyes = tile.eyes(self.params.stereo, self.params.ipd);
+        let passes = [(hdr, across, [1, 0], bloom::THRESHOLD), (across, blurred, [0, 1], 0.0)];
 
-        target.finish().unwrap();
+        for &(src, dst, axis, threshold) in passes.iter() {
+            let mut target =
+                SimpleFrameBuffer::new(display, dst).map_err(gl_object("bloom framebuffer"))?;
+            target.clear_color(0.0, 0.0, 0.0, 0.0);
+            for &(ref eye, viewport) in &eyes {
+                let (_, _, _, height) = eye.viewport;
+                let kernel = bloom::Kernel::new(self.params.bloom_radius, height);
+                let (left, bottom) = (viewport.left as i32, viewport.bottom as i32);
+                let uniforms = uniform! {
+                    src: nearest(src),
+                    axis: axis,
+                    bounds: [left, bottom, left + viewport.width as i32, bottom + viewport.height as i32],
+                    threshold: threshold,
+                    sigma: kernel.sigma,
+                    reach: kernel.reach,
+                };
+                self.post_draw(&mut target, program, &uniforms, Some(viewport))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a pass over the whole image, or over `viewport` of it.
+    fn post_draw<S, U>(
+        &self,
+        target: &mut S,
+        program: &glium::Program,
+        uniforms: &U,
+        viewport: Option<glium::Rect>,
+    ) -> Result<()>
+    where
+        S: Surface,
+        U: Uniforms,
+    {
+        let params = glium::DrawParameters {
+            viewport: viewport,
+            ..Default::default()
+        };
+        target
+            .draw(&self.buffers.0, &self.buffers.1, program, uniforms, &params)
+            .map_err(|e| Error::Draw(format!("{:?}", e)))
+    }
+
+    /// Renders a single frame offscreen and reads it back, for use when there
+    /// is no window to draw into.  The frame is traced a tile at a time, and
+    /// post-processed a tile at a time with enough of the image around each
+    /// for the glare.
+    pub fn render_image<F>(&self, display: &F, dims: (u32, u32), camera: &Camera, time: f32) -> Result<Frame>
+    where
+        F: Facade,
+    {
+        let (width, height) = dims;
+        let tiles = Tile::split(dims, self.params.tile);
+        let margin = self.margin(dims);
+
+        /* bloom reads past the edges of a tile, so then every tile is traced
+         * into a float copy of the image before any is post-processed.
+         * Otherwise each tile is tone mapped as soon as it is traced, and the
+         * floats are only kept for --hdr.  Both images have their rows from
+         * the top. */
+        let mut hdr = if self.params.hdr || margin > 0 {
+            Some(vec![0.0; width as usize * height as usize * 4])
+        } else {
+            None
+        };
+        let mut image = vec![0; width as usize * height as usize * 4];
+        let mut aovs = if self.params.aov { Some(Aovs::new(width, height)) } else { None };
+        for &tile in &tiles {
+            let (traced, tile_aovs) = self.trace_tile(display, camera, time, tile)?;
+            let r = tile.rect;
+            if let Some(ref mut hdr) = hdr {
+                let colours = read_floats(&traced);
+                let n = r.width as usize * 4;
+                for y in 0..r.height {
+                    /* GL rows start at the bottom */
+                    let row = (height - 1 - (r.bottom + y)) as usize;
+                    let from = y as usize * n;
+                    let to = (row * width as usize + r.left as usize) * 4;
+                    hdr[to..to + n].copy_from_slice(&colours.data[from..from + n]);
+                }
+            }
+            if margin == 0 {
+                self.post_tile(display, &mut image, &self.targets(display, traced)?, tile, tile)?;
+            }
+            if let (Some(aovs), Some(tile_aovs)) = (aovs.as_mut(), tile_aovs) {
+                /* their rows already start at the top */
+                let top = height - (r.bottom + r.height);
+                for y in 0..r.height {
+                    for x in 0..r.width {
+                        aovs.pixel_mut(r.left + x, top + y).copy_from_slice(tile_aovs.pixel(x, y));
+                    }
+                }
+            }
+        }
+
+        if margin > 0 {
+            /* kept above whenever there is a margin */
+            let hdr = hdr.as_ref().unwrap();
+            for &tile in &tiles {
+                let grown = tile.grow(margin);
+                let targets = self.targets(display, upload_floats(display, hdr, grown)?)?;
+                self.post_tile(display, &mut image, &targets, tile, grown)?;
+            }
+        }
+
+        Ok(Frame {
+            image: image::ImageBuffer::from_raw(width, height, image).unwrap(),
+            hdr: match hdr {
+                Some(hdr) if self.params.hdr => image::ImageBuffer::from_raw(width, height, hdr),
+                _ => None,
+            },
+            aovs: aovs,
+        })
+    }
+
+    /// Post-processes `targets`, which hold the part `grown` of the image,
+    /// and copies the part `tile` of the result into `image`.
+    fn post_tile<F>(
+        &self,
+        display: &F,
+        image: &mut [u8],
+        targets: &Targets,
+        tile: Tile,
+        grown: Tile,
+    ) -> Result<()>
+    where
+        F: Facade,
+    {
+        let (r, g) = (tile.rect, grown.rect);
+        let tex = glium::texture::SrgbTexture2d::empty_with_format(
+            display,
+            glium::texture::SrgbFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            g.width,
+            g.height,
+        )
+        .map_err(gl_object("tile texture"))?;
+        {
+            let mut target =
+                SimpleFrameBuffer::new(display, &tex).map_err(gl_object("tile framebuffer"))?;
+            self.post(display, &mut target, targets, grown)?;
+        }
+
+        let raw: glium::texture::RawImage2d<u8> = tex.read();
+        let (width, height) = tile.dims;
+        let n = r.width as usize * 4;
+        for y in 0..r.height {
+            let row = (height - 1 - (r.bottom + y)) as usize;
+            let from = (((r.bottom - g.bottom + y) * g.width + r.left - g.left) * 4) as usize;
+            let to = (row * width as usize + r.left as usize) * 4;
+            image[to..to + n].copy_from_slice(&raw.data[from..from + n]);
+        }
+        Ok(())
+    }
+
+    /// Traces `tile`, returning a texture of its colours and its AOVs if
+    /// they were asked for.
+    fn trace_tile<F>(
+        &self,
+        display: &F,
+        camera: &Camera,
+        time: f32,
+        tile: Tile,
+    ) -> Result<(Texture2d, Option<Aovs>)>
+    where
+        F: Facade,
+    {
+        let dims = (tile.rect.width, tile.rect.height);
+        let hdr = hdr_texture(display, dims)?;
+        let subpixels = &self.params.subpixels;
+        let weight = 1.0 / subpixels.len() as f32;
+
+        /* the AOVs come from the first ray of each pixel alone */
+        let aovs = if self.params.aov {
+            let ray = hdr_texture(display, dims)?;
+            let escape = hdr_texture(display, dims)?;
+            let drift = hdr_texture(display, dims)?;
+            {
+                let outputs = vec![
+                    ("color", hdr.to_color_attachment()),
+                    ("aov_ray", ray.to_color_attachment()),
+                    ("aov_escape", escape.to_color_attachment()),
+                    ("aov_drift", drift.to_color_attachment()),
+                ];
+                let mut target = MultiOutputFrameBuffer::new(display, outputs).map_err(gl_object("AOV framebuffer"))?;
+                target.clear_color(0.0, 0.0, 0.0, 0.0);
+                self.trace(&mut target, camera, time, tile, subpixels[0], weight)?;
+            }
+            Some(read_aovs(&ray, &escape, &drift))
+        } else {
+            None
+        };
+        {
+            let mut target =
+                SimpleFrameBuffer::new(display, &hdr).map_err(gl_object("float framebuffer"))?;
+            let rest = if aovs.is_some() {
+                &subpixels[1..]
+            } else {
+                target.clear_color(0.0, 0.0, 0.0, 0.0);
+                &subpixels[..]
+            };
+            for &subpixel in rest {
+                self.trace(&mut target, camera, time, tile, subpixel, weight)?;
+            }
+        }
+        Ok((hdr, aovs))
+    }
+
+    /// How far outside a tile post-processing it reads.
+    fn margin(&self, dims: (u32, u32)) -> u32 {
+        if self.bloom_program.is_none() {
+            return 0;
+        }
+        let eyes = self.params.stereo.eyes(self.params.ipd, dims);
+        eyes.iter()
+            .map(|eye| bloom::Kernel::new(self.params.bloom_radius, eye.viewport.3).reach as u32)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Creates a GL context with no window attached, for `render_image`.  It
+/// goes through EGL's surfaceless platform, so it works on machines without
+/// a display server, and with Mesa's llvmpipe on ones without a GPU.
+pub fn headless() -> Result<Rc<glium::backend::Context>> {
+    headless::context().map_err(Error::Context)
+}
+
+const ADD: glium::BlendingFunction = glium::BlendingFunction::Addition {
+    source: glium::LinearBlendingFactor::One,
+    destination: glium::LinearBlendingFactor::One,
+};
+
+/// The float images a frame is traced into and post-processed in.
+struct Targets {
+    hdr: Texture2d,
+    /// The highlights blurred along x, then along both axes, for the glare.
+    bloom: Option<(Texture2d, Texture2d)>,
+}
+
+/// The part `rect` of an image of size `dims` that a target holds, rows
+/// counting from the bottom as in GL.
+#[derive(Copy, Clone)]
+struct Tile {
+    dims: (u32, u32),
+    rect: glium::Rect,
+}
+
+impl Tile {
+    fn whole(dims: (u32, u32)) -> Self {
+        Tile {
+            dims: dims,
+            rect: glium::Rect {
+                left: 0,
+                bottom: 0,
+                width: dims.0,
+                height: dims.1,
+            },
+        }
+    }
+
+    /// Splits an image into tiles at most `size` pixels wide and high, or
+    /// leaves it whole if `size` is 0.
+    fn split(dims: (u32, u32), size: u32) -> Vec<Tile> {
+        if size == 0 {
+            return vec![Tile::whole(dims)];
+        }
+        let mut tiles = Vec::new();
+        for bottom in (0..dims.1).step_by(size as usize) {
+            for left in (0..dims.0).step_by(size as usize) {
+                tiles.push(Tile {
+                    dims: dims,
+                    rect: glium::Rect {
+                        left: left,
+                        bottom: bottom,
+                        width: size.min(dims.0 - left),
+                        height: size.min(dims.1 - bottom),
+                    },
+                });
+            }
+        }
+        tiles
+    }
+
+    /// The tile grown by `margin` pixels on each side, within the image.
+    fn grow(self, margin: u32) -> Tile {
+        let r = self.rect;
+        let (left, bottom) = (r.left.saturating_sub(margin), r.bottom.saturating_sub(margin));
+        let right = (r.left + r.width + margin).min(self.dims.0);
+        let top = (r.bottom + r.height + margin).min(self.dims.1);
+        Tile {
+            dims: self.dims,
+            rect: glium::Rect {
+                left: left,
+                bottom: bottom,
+                width: right - left,
+                height: top - bottom,
+            },
+        }
+    }
+
+    /// The eyes whose views reach into the tile, each with the part of the
+    /// tile its view covers.
+    fn eyes(self, stereo: Stereo, ipd: f32) -> Vec<(Eye, glium::Rect)> {
+        let r = self.rect;
+        stereo
+            .eyes(ipd, self.dims)
+            .into_iter()
+            .filter_map(|eye| {
+                let (left, bottom, width, height) = eye.viewport;
+                let (x0, y0) = (left.max(r.left), bottom.max(r.bottom));
+                let x1 = (left + width).min(r.left + r.width);
+                let y1 = (bottom + height).min(r.bottom + r.height);
+                if x0 < x1 && y0 < y1 {
+                    let viewport = glium::Rect {
+                        left: x0 - r.left,
+                        bottom: y0 - r.bottom,
+                        width: x1 - x0,
+                        height: y1 - y0,
+                    };
+                    Some((eye, viewport))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Samples `tex` a texel at a time, as `texelFetch` does.
+fn nearest(tex: &Texture2d) -> glium::uniforms::Sampler<'_, Texture2d> {
+    use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+
+    tex.sampled()
+        .minify_filter(MinifySamplerFilter::Nearest)
+        .magnify_filter(MagnifySamplerFilter::Nearest)
+}
+
+/// Reports that GL could not create `what`, for `map_err`.
+fn gl_object<E: fmt::Debug>(what: &'static str) -> impl FnOnce(E) -> Error {
+    move |e| Error::Resource(what.to_string(), format!("{:?}", e))
+}
+
+/// A float texture to trace colours or AOVs into.
+fn hdr_texture<F: Facade>(display: &F, dims: (u32, u32)) -> Result<Texture2d> {
+    Texture2d::empty_with_format(
+        display,
+        UncompressedFloatFormat::F32F32F32F32,
+        MipmapsOption::NoMipmap,
+        dims.0,
+        dims.1,
+    )
+    .map_err(gl_object("float texture"))
+}
+
+fn lut_texture<F: Facade>(display: &F, scene: &SceneConfig) -> Result<Texture2d> {
+    let raw = if scene.method == Method::Lut && !scene.flat {
+        let table = lut::table();
+        RawImage2d {
+            data: Cow::Owned(table.data.iter().flat_map(|t| t.iter().cloned()).collect()),
+            width: lut::WIDTH as u32,
+            height: lut::HEIGHT as u32,
+            format: ClientFormat::F32F32F32F32,
+        }
+    } else {
+        RawImage2d {
+            data: Cow::Owned(vec![0.0; 4]),
+            width: 1,
+            height: 1,
+            format: ClientFormat::F32F32F32F32,
+        }
+    };
+    Texture2d::with_format(display, raw, UncompressedFloatFormat::F32F32F32F32, MipmapsOption::NoMipmap)
+        .map_err(gl_object("ray table texture"))
+}
+
+/// Uploads the part `tile` of the RGBA `image`, whose rows start at the top,
+/// as a float texture.
+fn upload_floats<F: Facade>(display: &F, image: &[f32], tile: Tile) -> Result<Texture2d> {
+    let (r, width, height) = (tile.rect, tile.dims.0 as usize, tile.dims.1 as usize);
+    let mut data = Vec::with_capacity((r.width * r.height * 4) as usize);
+    for y in 0..r.height as usize {
+        /* GL rows start at the bottom */
+        let row = height - 1 - (r.bottom as usize + y);
+        let from = (row * width + r.left as usize) * 4;
+        data.extend_from_slice(&image[from..from + r.width as usize * 4]);
+    }
+    let raw = RawImage2d {
+        data: Cow::Owned(data),
+        width: r.width,
+        height: r.height,
+        format: ClientFormat::F32F32F32F32,
+    };
+    Texture2d::with_format(display, raw, UncompressedFloatFormat::F32F32F32F32, MipmapsOption::NoMipmap)
+        .map_err(gl_object("float texture"))
+}
+
+/// Reads back a float texture, bottom row first.
+fn read_floats(tex: &Texture2d) -> RawImage2d<'static, f32> {
+    let rect = glium::Rect {
+        left: 0,
+        bottom: 0,
+        width: tex.width(),
+        height: tex.height(),
+    };
+    let image = tex.main_level().first_layer().into_image(None).unwrap();
+    image.raw_read::<_, (f32, f32, f32, f32)>(&rect)
+}
+
+/// Unpacks the AOV textures into `output::CHANNELS` order, see the `aov`
+/// shader module.
+fn read_aovs(ray: &Texture2d, escape: &Texture2d, drift: &Texture2d) -> Aovs {
+    let (ray, escape, drift) = (read_floats(ray), read_floats(escape), read_floats(drift));
+
+    let mut aovs = Aovs::new(ray.width, ray.height);
+    for y in 0..ray.height {
+        for x in 0..ray.width {
+            /* GL rows start at the bottom */
+            let i = ((ray.height - 1 - y) * ray.width + x) as usize * 4;
+            let (r, e, d) = (&ray.data[i..i + 4], &escape.data[i..i + 4], &drift.data[i..i + 4]);
+            aovs.pixel_mut(x, y)
+                .copy_from_slice(&[r[0], r[1], e[0], e[1], e[2], r[2], r[3], d[0], d[1], e[3]]);
+        }
+    }
+    aovs
+}
+
+/// Uploads an equirectangular sky, see `resources::background`.
+fn equirect<F: Facade>(display: &F, path: Option<&str>) -> Result<Texture2d> {
+    let im = resources::background(path)?;
+    let imdim = im.dimensions();
+    let im = RawImage2d::from_raw_rgba_reversed(im.into_raw().as_slice(), imdim);
+    /* half floats keep the range of HDR skies at half the memory */
+    Texture2d::with_format(
+        display,
+        im,
+        UncompressedFloatFormat::F16F16F16F16,
+        MipmapsOption::AutoGeneratedMipmaps,
+    )
+    .map_err(|e| Error::Asset("background image".to_string(), format!("{:?}", e)))
+}
+
+/// Uploads a cubemap sky, see `resources::cubemap`.
+fn cubemap<F: Facade>(display: &F, path: &str) -> Result<Cubemap> {
+    use glium::texture::CubeLayer::*;
+
+    let err = |e: String| Error::Asset(path.to_string(), e);
+    let faces = resources::cubemap(path)?;
+    let dim = faces[0].width();
+    let cube = Cubemap::empty_with_format(
+        display,
+        UncompressedFloatFormat::F16F16F16F16,
+        MipmapsOption::EmptyMipmaps,
+        dim,
+    )
+    .map_err(|e| err(format!("{:?}", e)))?;
+
+    /* glium can't write to cubemaps directly, so each face goes through a
+     * 2D texture and is copied into place.  GL expects the top rows first,
+     * as the images already are. */
+    let layers = [PositiveX, NegativeX, PositiveY, NegativeY, PositiveZ, NegativeZ];
+    for (face, &layer) in faces.into_iter().zip(layers.iter()) {
+        let im = RawImage2d::from_raw_rgba(face.into_raw(), (dim, dim));
+        let tex = Texture2d::with_format(
+            display,
+            im,
+            UncompressedFloatFormat::F16F16F16F16,
+            MipmapsOption::NoMipmap,
+        )
+        .map_err(|e| err(format!("{:?}", e)))?;
+        let target = glium::framebuffer::SimpleFrameBuffer::new(display, cube.main_level().image(layer))
+            .map_err(|e| err(format!("{:?}", e)))?;
+        tex.as_surface().fill(&target, glium::uniforms::MagnifySamplerFilter::Nearest);
+    }
+    unsafe {
+        cube.generate_mipmaps();
     }
+    Ok(cube)
 }
 
 impl RenderParams {
-    fn new(args: &ArgMatches) -> Self {
+    fn new(scene: &SceneConfig) -> Self {
         RenderParams {
-            iter: args.value_of("iter").unwrap().parse::<i32>().unwrap(),
-            time_step: args.value_of("timestep").unwrap().parse::<f32>().unwrap(),
+            iter: scene.iter,
+            time_step: scene.time_step,
+            stereo: scene.stereo,
+            ipd: scene.ipd,
+            aov: scene.aovs(),
+            hdr: scene.hdr,
+            bloom_radius: scene.bloom_radius,
+            subpixels: scene.subpixels(),
+            tile: scene.tile,
         }
     }
 }
@@ -0,0 +1,138 @@
+extern crate image;
+
+use image::{imageops, DynamicImage, ImageFormat, Rgba32FImage};
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use error::{Error, Result};
+
+/// Loads the equirectangular background image from `path`, or the bundled
+/// sky if none is given, as linear colour.  Anything the `image` crate can
+/// decode works; HDR and EXR images keep their full range.
+pub fn background(path: Option<&str>) -> Result<Rgba32FImage> {
+    match path {
+        Some(path) => open(Path::new(path)),
+        None => {
+            let bytes = &include_bytes!("../resources/bg-small.jpg")[..];
+            load("background image", bytes, ImageFormat::Jpeg).map(linear)
+        }
+    }
+}
+
+/// Loads the faces of a cubemap sky as linear colour, in GL order (+x, -x,
+/// +y, -y, +z, -z) with their top rows first.  `path` is either a directory
+/// holding an image per face or a single image with the faces laid out as a
+/// cross around +z.
+pub fn cubemap(path: &str) -> Result<Vec<Rgba32FImage>> {
+    let err = |msg: &str| Error::Asset(path.to_string(), msg.to_string());
+    let faces = if Path::new(path).is_dir() {
+        let mut faces = Vec::with_capacity(6);
+        for names in &FACE_NAMES {
+            faces.push(open(&face_file(path, names)?)?);
+        }
+        faces
+    } else {
+        cross(open(Path::new(path))?).ok_or_else(|| err("not laid out as a 4x3 or 3x4 cross"))?
+    };
+
+    let dim = faces[0].width();
+    if faces.iter().any(|f| f.dimensions() != (dim, dim)) {
+        return Err(err("cubemap faces must be square and all the same size"));
+    }
+    Ok(faces)
+}
+
+/// Loads the accretion disk texture.
+pub fn disk() -> Result<image::RgbaImage> {
+    let bytes = &include_bytes!("../resources/ad.png")[..];
+    load("accretion disk texture", bytes, ImageFormat::Png).map(|im| im.to_rgba8())
+}
+
+/// File names, without extension, accepted for each face of a cubemap.
+const FACE_NAMES: [&'static [&'static str]; 6] = [
+    &["px", "posx", "right"],
+    &["nx", "negx", "left"],
+    &["py", "posy", "top"],
+    &["ny", "negy", "bottom"],
+    &["pz", "posz", "front"],
+    &["nz", "negz", "back"],
+];
+
+fn face_file(dir: &str, names: &[&str]) -> Result<PathBuf> {
+    let err = |msg: String| Error::Asset(dir.to_string(), msg);
+    for entry in fs::read_dir(dir).map_err(|e| err(e.to_string()))? {
+        let path = entry.map_err(|e| err(e.to_string()))?.path();
+        let stem = path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_lowercase());
+        if stem.is_some_and(|s| names.contains(&s.as_str())) {
+            return Ok(path);
+        }
+    }
+    Err(err(format!("no cubemap face named {}", names.join(" or "))))
+}
+
+/// Cuts the faces out of a horizontal cross:
+///
+/// ```text
+///       +y
+///   -x  +z  +x  -z
+///       -y
+/// ```
+///
+/// or a vertical one, which has -z upside down below -y instead.
+fn cross(im: Rgba32FImage) -> Option<Vec<Rgba32FImage>> {
+    let (w, h) = im.dimensions();
+    /* (column, row) of each face */
+    let (size, cells) = if w * 3 == h * 4 {
+        (w / 4, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)])
+    } else if w * 4 == h * 3 {
+        (w / 3, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)])
+    } else {
+        return None;
+    };
+
+    let mut faces: Vec<_> = cells
+        .iter()
+        .map(|&(x, y)| imageops::crop_imm(&im, x * size, y * size, size, size).to_image())
+        .collect();
+    if h > w {
+        faces[5] = imageops::rotate180(&faces[5]);
+    }
+    Some(faces)
+}
+
+fn open(path: &Path) -> Result<Rgba32FImage> {
+    image::open(path)
+        .map(linear)
+        .map_err(|e| Error::Asset(path.display().to_string(), e.to_string()))
+}
+
+fn load(name: &str, bytes: &[u8], format: ImageFormat) -> Result<DynamicImage> {
+    image::load(Cursor::new(bytes), format)
+        .map_err(|e| Error::Asset(name.to_string(), e.to_string()))
+}
+
+/// Float images are already linear, integer ones are sRGB encoded.
+fn linear(im: DynamicImage) -> Rgba32FImage {
+    match im {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => im.to_rgba32f(),
+        _ => {
+            let mut im = im.to_rgba32f();
+            for p in im.pixels_mut() {
+                for c in &mut p.0[..3] {
+                    *c = from_srgb(*c);
+                }
+            }
+            im
+        }
+    }
+}
+
+pub fn from_srgb(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
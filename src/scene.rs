@@ -0,0 +1,413 @@
+//! Scene descriptions.  A scene can be loaded from a TOML file, whose keys
+//! are the fields of `SceneConfig`; keys left out of the file take the
+//! defaults below, see `scenes/default.toml`.  The binary's command line
+//! flags override the values of a loaded scene.
+
+extern crate toml;
+
+use std::fs;
+
+use error::{Error, Result};
+use kerr;
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    /// A perspective view of `fov` degrees.
+    Pinhole,
+    /// The whole sky around the camera, as an equirectangular panorama.
+    Equirect,
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stereo {
+    Mono,
+    /// The left eye's view on the left half of the image.
+    SideBySide,
+    /// The left eye's view on the top half of the image.
+    OverUnder,
+}
+
+/// How traced colours, which have no upper limit, are brought into the range
+/// of the output image.
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMap {
+    /// Clips each channel at 1.
+    Clamp,
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Filmic,
+}
+
+/// One eye's view within the output image.
+pub struct Eye {
+    /// Offset from the camera along its right vector.
+    pub offset: f32,
+    /// `(left, bottom, width, height)`, counting rows up from the bottom of
+    /// the image as GL does.
+    pub viewport: (u32, u32, u32, u32),
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Flat,
+    Verlet,
+    Rk4,
+    Rk45,
+    /// Looks the path of each ray up in a table of the orbits around a
+    /// Schwarzschild hole instead of integrating it, see `lut`.  Cameras
+    /// inside the photon sphere see the rays that would circle there before
+    /// falling in as falling in at once.
+    Lut,
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Background {
+    Black,
+    Img,
+    Cube,
+    Stars,
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Disk {
+    None,
+    White,
+    Img,
+    Dyno,
+    Blackbody,
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Surface {
+    Black,
+    Checkered,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SceneConfig {
+    pub width: u32,
+    pub height: u32,
+    pub projection: Projection,
+    /// Horizontal field of view in degrees, for the pinhole projection.
+    pub fov: f32,
+    pub stereo: Stereo,
+    /// Distance between the eyes of a stereo view.
+    pub ipd: f32,
+    /// Simulation time of headless and CPU renders, in seconds.
+    pub time: f32,
+    /// Headless and CPU renders average `supersample`^2 rays per pixel, on
+    /// a grid across it.  The window always traces one.
+    pub supersample: u32,
+    /// Headless renders are traced in tiles at most `tile` pixels square, or
+    /// whole if it is 0.  Tiles allow images larger than the GPU can draw at
+    /// once, and keep long traces within the driver's time limits.
+    pub tile: u32,
+    /// Writes auxiliary outputs next to rendered images, see `output`.
+    pub aov: bool,
+    /// Measures how far each ray drifts from the quantities its geodesic
+    /// conserves and draws that instead of the scene, see `output`.
+    pub drift: bool,
+    pub tone_map: ToneMap,
+    /// Scales colours by `2^exposure` before tone mapping.
+    pub exposure: f32,
+    /// Writes the traced colours next to rendered images as an EXR, before
+    /// exposure and tone mapping.
+    pub hdr: bool,
+    /// Strength of the glare around colours brighter than white, zero for
+    /// none, see `bloom`.
+    pub bloom: f32,
+    /// Width of the glare as a fraction of the height of the view.
+    pub bloom_radius: f32,
+
+    /// Turns off relativistic distortion.
+    pub flat: bool,
+    pub method: Method,
+    pub iter: i32,
+    pub time_step: f32,
+    pub tolerance: f32,
+    pub min_step: f32,
+    pub max_step: f32,
+    /// The dimensionless spin `a / M`.  Any nonzero spin traces a Kerr hole
+    /// with the Kerr integrator, whatever `method` says.
+    pub spin: Option<f32>,
+    pub surface: Surface,
+
+    pub bg: Background,
+    /// The sky: an equirectangular image for the `img` background, replacing
+    /// the bundled one, or for `cube` a directory of six faces or a single
+    /// image with the faces laid out as a cross.
+    pub bg_image: Option<String>,
+    pub bg_ratio: f32,
+    /// Seed of the `stars` background.
+    pub seed: u32,
+    /// Adds a galactic band to the `stars` background.
+    pub galaxy: bool,
+
+    pub disk: Disk,
+    /// Defaults to the ISCO.
+    pub inner_radius: Option<f32>,
+    pub outer_radius: f32,
+    /// Mass of the hole in solar masses, for the blackbody disk.
+    pub mass: f32,
+    /// Accretion rate as a fraction of the Eddington rate, for the
+    /// blackbody disk.
+    pub mdot: f32,
+    pub redshift: bool,
+    pub doppler: bool,
+    /// Opacity of the disk in each image of it: the `n`th weight scales the
+    /// disk where a ray crosses its plane for the `n`th time, seen as the
+    /// image of order `n - 1`, and the last weight every later crossing.
+    /// `[1.0, 0.0]` keeps only the direct image and `[0.0, 1.0, 0.0]` only
+    /// the first photon ring.
+    pub order_weights: Vec<f32>,
+}
+
+impl Default for SceneConfig {
+    fn default() -> Self {
+        SceneConfig {
+            width: 1024,
+            height: 768,
+            projection: Projection::Pinhole,
+            fov: 90.0,
+            stereo: Stereo::Mono,
+            ipd: 0.2,
+            time: 0.0,
+            supersample: 1,
+            tile: 0,
+            aov: false,
+            drift: false,
+            tone_map: ToneMap::Clamp,
+            exposure: 0.0,
+            hdr: false,
+            bloom: 0.0,
+            bloom_radius: 0.01,
+
+            flat: false,
+            method: Method::Rk4,
+            iter: 1000,
+            time_step: 0.64,
+            tolerance: 0.0001,
+            min_step: 0.01,
+            max_step: 2.0,
+            spin: None,
+            surface: Surface::Black,
+
+            bg: Background::Img,
+            bg_image: None,
+            bg_ratio: 0.5,
+            seed: 0,
+            galaxy: false,
+
+            disk: Disk::Dyno,
+            inner_radius: None,
+            outer_radius: 15.0,
+            mass: 10.0,
+            mdot: 0.1,
+            redshift: false,
+            doppler: false,
+            order_weights: vec![1.0],
+        }
+    }
+}
+
+impl SceneConfig {
+    pub fn load(path: &str) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).map_err(|e| Error::Scene(path.to_string(), e.to_string()))?;
+        toml::from_str(&text).map_err(|e| Error::Scene(path.to_string(), e.to_string()))
+    }
+
+    /// Checks the values that would otherwise produce a broken shader or a
+    /// meaningless image.
+    pub fn validate(&self) -> Result<()> {
+        fn check(ok: bool, msg: String) -> Result<()> {
+            if ok {
+                Ok(())
+            } else {
+                Err(Error::Arg(msg))
+            }
+        }
+
+        check(
+            self.width > 0 && self.height > 0,
+            format!(
+                "image size must be positive, got {}x{}",
+                self.width, self.height
+            ),
+        )?;
+        let (eye_width, eye_height) = self.eye_dims();
+        check(
+            eye_width > 0 && eye_height > 0,
+            format!(
+                "image size {}x{} leaves no room for each eye",
+                self.width, self.height
+            ),
+        )?;
+        check(
+            self.fov > 0.0 && self.fov < 180.0,
+            format!("fov must be between 0 and 180 degrees, got {}", self.fov),
+        )?;
+        check(
+            self.ipd >= 0.0,
+            format!("ipd must not be negative, got {}", self.ipd),
+        )?;
+        check(
+            self.supersample > 0,
+            format!("supersample must be positive, got {}", self.supersample),
+        )?;
+        check(
+            self.bloom >= 0.0,
+            format!("bloom must not be negative, got {}", self.bloom),
+        )?;
+        check(
+            self.bloom_radius > 0.0,
+            format!("bloom radius must be positive, got {}", self.bloom_radius),
+        )?;
+        check(
+            self.iter > 0,
+            format!("iter must be positive, got {}", self.iter),
+        )?;
+        check(
+            self.time_step > 0.0,
+            format!("time step must be positive, got {}", self.time_step),
+        )?;
+        check(
+            self.tolerance > 0.0,
+            format!("tolerance must be positive, got {}", self.tolerance),
+        )?;
+        check(
+            self.min_step > 0.0 && self.min_step <= self.max_step,
+            format!(
+                "step limits must satisfy 0 < min <= max, got {} and {}",
+                self.min_step, self.max_step
+            ),
+        )?;
+        check(
+            self.method != Method::Lut || self.spin.is_none(),
+            "the lut method only traces a Schwarzschild hole".to_string(),
+        )?;
+        let integrated = !self.flat && self.method != Method::Flat && self.method != Method::Lut;
+        check(
+            !self.drift || (integrated && self.spin.is_none()),
+            "drift is only measured on integrated paths around a Schwarzschild hole".to_string(),
+        )?;
+        check(
+            self.spin().abs() <= 1.0,
+            format!("spin must be between -1 and 1, got {}", self.spin()),
+        )?;
+        check(
+            self.inner_radius() >= 0.0 && self.inner_radius() < self.outer_radius,
+            format!(
+                "disk radii must satisfy 0 <= inner < outer, got {} and {}",
+                self.inner_radius(),
+                self.outer_radius
+            ),
+        )?;
+        if self.bg == Background::Cube {
+            self.cube_image()?;
+        }
+        check(
+            self.bg_ratio >= 0.0,
+            format!("bg ratio must not be negative, got {}", self.bg_ratio),
+        )?;
+        check(
+            self.mass > 0.0,
+            format!("mass must be positive, got {}", self.mass),
+        )?;
+        check(
+            self.mdot >= 0.0,
+            format!("mdot must not be negative, got {}", self.mdot),
+        )?;
+        check(
+            !self.order_weights.is_empty() && self.order_weights.iter().all(|w| (0.0..=1.0).contains(w)),
+            format!("order weights must be between 0 and 1, got {:?}", self.order_weights),
+        )
+    }
+
+    /// The dimensionless spin, 0 for a Schwarzschild hole.
+    pub fn spin(&self) -> f32 {
+        self.spin.unwrap_or(0.0)
+    }
+
+    /// Whether the hole spins, in which case `method` is ignored.
+    pub fn is_kerr(&self) -> bool {
+        self.spin() != 0.0
+    }
+
+    /// Whether renders produce AOVs, which they do for reporting drift even
+    /// if they weren't asked for.
+    pub fn aovs(&self) -> bool {
+        self.aov || self.drift
+    }
+
+    /// The path of the `cube` background's faces.
+    pub fn cube_image(&self) -> Result<&str> {
+        self.bg_image
+            .as_deref()
+            .ok_or_else(|| Error::Arg("the cube background needs a bg image".to_string()))
+    }
+
+    pub fn inner_radius(&self) -> f32 {
+        self.inner_radius.unwrap_or_else(|| kerr::isco(self.spin()))
+    }
+
+    pub fn dims(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The size of the view each eye gets.
+    pub fn eye_dims(&self) -> (u32, u32) {
+        let (_, _, width, height) = self.stereo.eyes(self.ipd, self.dims())[0].viewport;
+        (width, height)
+    }
+
+    /// Where the rays of an exported pixel pass, as offsets from its centre
+    /// between -0.5 and 0.5.  The one nearest the centre comes first, which
+    /// is the ray the AOVs are taken from.
+    pub fn subpixels(&self) -> Vec<(f32, f32)> {
+        let n = self.supersample;
+        let at = |i: u32| (i as f32 + 0.5) / n as f32 - 0.5;
+        let mut offsets: Vec<_> = (0..n * n).map(|i| (at(i % n), at(i / n))).collect();
+        /* the sort is stable, keeping the grid order among equals */
+        offsets.sort_by(|a, b| (a.0 * a.0 + a.1 * a.1).partial_cmp(&(b.0 * b.0 + b.1 * b.1)).unwrap());
+        offsets
+    }
+}
+
+impl Stereo {
+    /// Splits an image of size `dims` between the eyes, left eye first.
+    pub fn eyes(self, ipd: f32, dims: (u32, u32)) -> Vec<Eye> {
+        let (width, height) = dims;
+        let eye = |offset, viewport| Eye {
+            offset: offset,
+            viewport: viewport,
+        };
+        match self {
+            Stereo::Mono => vec![eye(0.0, (0, 0, width, height))],
+            Stereo::SideBySide => {
+                let half = width / 2;
+                vec![
+                    eye(-ipd / 2.0, (0, 0, half, height)),
+                    eye(ipd / 2.0, (half, 0, half, height)),
+                ]
+            }
+            Stereo::OverUnder => {
+                let half = height / 2;
+                vec![
+                    eye(-ipd / 2.0, (0, height - half, width, half)),
+                    eye(ipd / 2.0, (0, 0, width, half)),
+                ]
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
-extern crate clap;
 extern crate glium;
 
-use clap::ArgMatches;
+use error::{Error, Result, ShaderError};
+use scene::SceneConfig;
+
+/// Source string number given to the vertex shader with `#line`, so the
+/// compiler log tells the two stages apart.
+const VERT_SOURCE: u32 = 1;
 
 pub struct Shader {
     vert_shader: String,
@@ -9,34 +13,157 @@ pub struct Shader {
 }
 
 impl Shader {
-    pub fn construct(args: &ArgMatches) -> Self {
+    pub fn construct(scene: &SceneConfig) -> Self {
         Shader {
-            vert_shader: Shader::construct_vert_shader(args),
-            frag_shader: Shader::construct_frag_shader(args),
+            vert_shader: Shader::construct_vert_shader(scene),
+            frag_shader: Shader::construct_frag_shader(scene),
         }
     }
 
     #[allow(unused_variables)]
-    fn construct_vert_shader(args: &ArgMatches) -> String {
-        vert_shader::gen_shader(args)
+    fn construct_vert_shader(scene: &SceneConfig) -> String {
+        set_source(vert_shader::gen_shader(scene), VERT_SOURCE)
+    }
+
+    fn construct_frag_shader(scene: &SceneConfig) -> String {
+        frag_shader::gen_shader(scene)
+    }
+
+    /// The pass bringing the traced colours, written to a float texture,
+    /// into the range of the output.
+    pub fn tone_map(scene: &SceneConfig) -> Self {
+        Shader {
+            vert_shader: set_source(POST_VERT_SHADER.to_string(), VERT_SOURCE),
+            frag_shader: tone_shader::gen_shader(scene),
+        }
+    }
+
+    /// The pass blurring the traced colours along one axis for the glare,
+    /// see `bloom`.
+    pub fn bloom(scene: &SceneConfig) -> Self {
+        Shader {
+            vert_shader: set_source(POST_VERT_SHADER.to_string(), VERT_SOURCE),
+            frag_shader: bloom_shader::gen_shader(scene),
+        }
+    }
+
+    /// The generated GLSL of the vertex stage.
+    pub fn vert_source(&self) -> &str {
+        &self.vert_shader
     }
 
-    fn construct_frag_shader(args: &ArgMatches) -> String {
-        frag_shader::gen_shader(args)
+    /// The generated GLSL of the fragment stage.
+    pub fn frag_source(&self) -> &str {
+        &self.frag_shader
     }
 
-    pub fn compile<F>(self, display: &F) -> glium::Program
+    pub fn compile<F>(self, display: &F) -> Result<glium::Program>
     where
         F: glium::backend::Facade,
     {
+        use glium::program::ProgramCreationError::*;
+
         let res = glium::Program::from_source(display, &self.vert_shader, &self.frag_shader, None);
-        match res {
-            Ok(t) => t,
-            Err(e) => {
-                panic!("{}", e);
+        res.map_err(|e| match e {
+            CompilationError(log) => Error::Shader(self.map_log(&log)),
+            LinkingError(log) => Error::Shader(
+                log.lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(|l| ShaderError {
+                        source: None,
+                        message: l.trim().to_string(),
+                    })
+                    .collect(),
+            ),
+            e => Error::Resource("shader program".to_string(), e.to_string()),
+        })
+    }
+
+    /// Traces each line of a compiler log back to the generator module that
+    /// produced the code it complains about.
+    fn map_log(&self, log: &str) -> Vec<ShaderError> {
+        log.lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| ShaderError {
+                source: log_location(l).map(|(id, line)| {
+                    if id == VERT_SOURCE {
+                        find_module(&self.vert_shader, "vert_shader", line)
+                    } else {
+                        find_module(&self.frag_shader, "frag_shader", line)
+                    }
+                }),
+                message: l.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Wraps generated code in comments naming the module that produced it, which
+/// `find_module` uses to map compile errors back to their generator.
+fn section(module: &str, code: String) -> String {
+    format!("/* begin {} */\n{}\n/* end {} */", module, code, module)
+}
+
+/// Returns the generator module that produced line `line` of `source`, and
+/// the line within that module's output.  Lines outside any section belong
+/// to the template, `top`.
+fn find_module(source: &str, top: &'static str, line: usize) -> (&'static str, usize) {
+    const MODULES: [&'static str; 9] = ["proj", "bg", "bh", "trace", "ad", "iter", "aov", "tone", "bloom"];
+
+    let mut current = (top, 0);
+    for (i, l) in source.lines().take(line).enumerate() {
+        let l = l.trim();
+        if l.starts_with("/* end ") {
+            current = (top, 0);
+        } else if l.starts_with("/* begin ") {
+            if let Some(m) = MODULES.iter().find(|&&m| l == format!("/* begin {} */", m)) {
+                current = (*m, i + 1);
+            }
+        }
+    }
+    (current.0, line - current.1)
+}
+
+/// Finds the `(source string, line)` a driver log line refers to.  Drivers
+/// write this as `0:12(5)` (Mesa), `0(12)` (NVIDIA) or `0:12:` (AMD and
+/// Intel), so this takes the first pair of numbers split by `:` or `(`.
+fn log_location(l: &str) -> Option<(u32, usize)> {
+    let bytes = l.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i > start && i + 1 < bytes.len() && (bytes[i] == b':' || bytes[i] == b'(') {
+            let mid = i + 1;
+            let mut end = mid;
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+            if end > mid {
+                return Some((l[start..i].parse().ok()?, l[mid..end].parse().ok()?));
             }
         }
+        i = if i > start { i } else { i + 1 };
     }
+    None
+}
+
+/// Marks the code after the `#version` line as source string `id`, keeping
+/// its line numbers unchanged.
+fn set_source(shader: String, id: u32) -> String {
+    let mut out = String::new();
+    for (i, l) in shader.lines().enumerate() {
+        out += l;
+        out += "\n";
+        if l.trim().starts_with("#version") {
+            /* the line after the directive is line i + 3 */
+            out += &format!("#line {} {}\n", i + 3, id);
+        }
+    }
+    out
 }
 
 const DEFAULT_VERT_SHADER: &'static str = r#"
@@ -63,10 +190,22 @@ void main() {
 
 "#;
 
+/// Vertex shader of passes working on a whole image, which read it with
+/// `texelFetch` at `gl_FragCoord`.
+const POST_VERT_SHADER: &'static str = r#"
+#version 330
+
+in vec2 pos;
+
+void main() {
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
+"#;
+
 #[allow(unused_variables)]
 mod vert_shader {
-    use clap::ArgMatches;
-    pub fn gen_shader(args: &ArgMatches) -> String {
+    use scene::SceneConfig;
+    pub fn gen_shader(scene: &SceneConfig) -> String {
         format!(
             r#"
             {preamble}
@@ -76,7 +215,7 @@ mod vert_shader {
             {main}
         "#,
             preamble = PREAMBLE,
-            params = params(args),
+            params = params(scene),
             main = MAIN
         )
     }
@@ -90,23 +229,27 @@ mod vert_shader {
 
     uniform float height_ratio; // height / width
     uniform mat3 facing;
+    /* where the quad lies in the view of the eye, which may be cut into
+       tiles or moved to trace another ray through each pixel */
+    uniform vec2 pos_scale;
+    uniform vec2 pos_offset;
     "#;
 
     const MAIN: &'static str = r#"
     void main() {
-        float x = pos.x * fov_ratio;
-        float y = pos.y * fov_ratio * height_ratio;
+        vec2 p = pos * pos_scale + pos_offset;
+        float x = p.x * fov_ratio;
+        float y = p.y * fov_ratio * height_ratio;
         dir_v = facing * vec3(x, y, 1.0);
-        pos_v = pos;
+        pos_v = p;
 
         gl_Position = vec4(pos, 0.0, 1.0);
     }
     "#;
 
-    fn params(args: &ArgMatches) -> String {
+    fn params(scene: &SceneConfig) -> String {
         use std::f32;
-        let fov: f32 = args.value_of("fov").unwrap().parse().unwrap();
-        let rat = (fov / 2.0f32 / 180.0f32 * f32::consts::PI).tan();
+        let rat = (scene.fov / 2.0f32 / 180.0f32 * f32::consts::PI).tan();
         format!(
             r#"
             const float fov_ratio = {};
@@ -118,14 +261,21 @@ mod vert_shader {
 
 #[allow(unused_variables)]
 mod frag_shader {
-    use clap::ArgMatches;
-    pub fn gen_shader(args: &ArgMatches) -> String {
+    use super::section;
+    use scene::SceneConfig;
+    pub fn gen_shader(scene: &SceneConfig) -> String {
         format!(
             r#"
 {preamble}
 
+{aov_params}
+
+{proj_func}
+
 {bg_func}
 
+{bh_params}
+
 {trace_params}
 
 {ad_params}
@@ -133,16 +283,18 @@ mod frag_shader {
 void main() {{
     float alpha_rem = 1.0;
     vec4 ccolor = vec4(0.0, 0.0, 0.0, 0.0);
-    vec3 dir = normalize(dir_v);
-    vec3 pos = src;
+    vec3 dir = ray_dir();
+    vec3 pos = ray_src();
 
     /* closest approach to BH */
-    float min_dist = length(cross(dir, src));
+    float min_dist = length(cross(dir, pos));
 
     {loop_vars}
 
     {trace_vars}
 
+    {aov_vars}
+
     {loop_cond} {{
         vec3 npos, ndir;
 
@@ -153,24 +305,36 @@ void main() {{
 
         pos = npos;
         dir = ndir;
+
+        {aov_step}
+
+        {loop_step}
     }}
 
     ccolor += alpha_rem * bg_col(dir);
 
-    color = ccolor;
+    color = ccolor * weight;
+    {aov_write}
 }}
 
     "#,
             preamble = PREAMBLE,
-            bg_func = bg::func(args),
-            trace_params = trace::params(args),
-            ad_params = ad::params(args),
-            loop_vars = iter::vars(args),
-            trace_vars = trace::vars(args),
-            loop_cond = iter::cond(args),
-            update_func = trace::update(args),
-            bh_check = bh::check(args),
-            ad_check = ad::check(args)
+            aov_params = section("aov", aov::params(scene)),
+            proj_func = section("proj", proj::func(scene)),
+            bg_func = section("bg", bg::func(scene)),
+            bh_params = section("bh", bh::params(scene)),
+            trace_params = section("trace", trace::params(scene)),
+            ad_params = section("ad", ad::params(scene)),
+            loop_vars = section("iter", iter::vars(scene)),
+            trace_vars = section("trace", trace::vars(scene)),
+            loop_cond = section("iter", iter::cond(scene)),
+            loop_step = section("iter", iter::step(scene)),
+            update_func = section("trace", trace::update(scene)),
+            bh_check = section("bh", bh::check(scene)),
+            ad_check = section("ad", ad::check(scene)),
+            aov_vars = section("aov", aov::vars(scene)),
+            aov_step = section("aov", aov::step(scene)),
+            aov_write = section("aov", aov::write(scene))
         )
     }
 
@@ -186,10 +350,13 @@ const float G = 1.0;
 
 uniform vec3 src;
 uniform float time;
+/* the share of the pixel this ray's colour makes up, 1 unless
+   supersampling */
+uniform float weight;
 
 in vec3 dir_v;
-in vec3 pos_v;
-out vec4 color;
+in vec2 pos_v;
+layout(location = 0) out vec4 color;
 
 float atan2(float y, float x) {
     return x == 0.0 ? sign(y) * M_PI / 2 : atan(y, x);
@@ -218,33 +385,127 @@ float ts_func(float ts, vec3 pos) {
 }
 "#;
 
+    mod proj {
+        use scene::{Projection, SceneConfig};
+
+        pub fn func(scene: &SceneConfig) -> String {
+            let proj = match scene.projection {
+                Projection::Pinhole => PINHOLE,
+                Projection::Equirect => EQUIRECT,
+            };
+            format!("{}\n{}", EYE, proj)
+        }
+
+        const EYE: &'static str = r#"
+uniform mat3 facing;
+/* offset of the eye from src along the camera's right vector, 0 unless
+   rendering in stereo */
+uniform float eye;"#;
+
+        const PINHOLE: &'static str = r#"
+vec3 ray_dir() {
+    return normalize(dir_v);
+}
+
+vec3 ray_src() {
+    return src + facing * vec3(eye, 0.0, 0.0);
+}"#;
+
+        /* directions have to be found per fragment, interpolating them
+         * across the screen doesn't wrap around the sphere */
+        const EQUIRECT: &'static str = r#"
+/* the longitude and latitude around the camera, laid out like the
+   equirectangular background */
+vec3 ray_dir() {
+    float yaw = pos_v.x * M_PI;
+    float pitch = pos_v.y * M_PI / 2.0;
+    return facing * vec3(cos(pitch) * sin(yaw), sin(pitch), cos(pitch) * cos(yaw));
+}
+
+/* omnidirectional stereo: the eyes sit on a circle around the camera,
+   each looking out at right angles to the line between them */
+vec3 ray_src() {
+    float yaw = pos_v.x * M_PI;
+    return src + facing * (eye * vec3(cos(yaw), 0.0, -sin(yaw)));
+}"#;
+    }
+
     mod bg {
-        use clap::ArgMatches;
+        use cgmath::vec3;
+        use scene::{Background, SceneConfig};
+        use stars::{self, Starfield};
+
         enum Type {
             Black,
             Texture,
+            Cube,
+            Stars,
         }
 
-        pub fn func(args: &ArgMatches) -> String {
-            let s = args.value_of("bg").unwrap_or("img");
-            let rat: f32 = args.value_of("bgrat").unwrap().parse().unwrap();
+        pub fn func(scene: &SceneConfig) -> String {
+            let ty = match scene.bg {
+                Background::Img => Type::Texture,
+                Background::Cube => Type::Cube,
+                Background::Stars => Type::Stars,
+                Background::Black => Type::Black,
+            };
+            let mut extra = String::new();
+            if let Type::Stars = ty {
+                extra += &star_params(scene);
+            }
             format!(
                 r#"
                 const float BG_RAT = {rat};
+                {extra}
 
                 {func}
             "#,
-                rat = rat,
-                func = BGS[(match s {
-                    "img" => Type::Texture,
-                    "black" => Type::Black,
-                    _ => panic!("Invalid bg type"),
-                }) as usize]
-                    .to_string()
+                rat = scene.bg_ratio,
+                extra = extra,
+                func = BGS[ty as usize]
+            )
+        }
+
+        fn star_params(scene: &SceneConfig) -> String {
+            let field = Starfield::new(scene);
+            let cols: Vec<String> = field
+                .colours
+                .iter()
+                .map(|c| format!("vec3({:e}, {:e}, {:e})", c[0], c[1], c[2]))
+                .collect();
+            let normal = field.galaxy.unwrap_or(vec3(0.0, 1.0, 0.0));
+            format!(
+                r#"
+                const uint STAR_SEED = {}u;
+                const float STAR_SIZE = {:e};
+                const float STAR_SHELL = {:e};
+                const float STAR_DENSITY = {:e};
+                const float STAR_MIN_BRIGHTNESS = {:e};
+                const vec3 STAR_COL[{}] = vec3[]({});
+                const bool GALAXY = {};
+                const vec3 GALAXY_NORMAL = vec3({:e}, {:e}, {:e});
+                const float GALAXY_WIDTH = {:e};
+                const float GALAXY_STARS = {:e};
+                const float GALAXY_GLOW = {:e};
+                "#,
+                field.seed,
+                field.size,
+                stars::SHELL,
+                stars::DENSITY,
+                stars::MIN_BRIGHTNESS,
+                cols.len(),
+                cols.join(", "),
+                field.galaxy.is_some(),
+                normal.x,
+                normal.y,
+                normal.z,
+                stars::GALAXY_WIDTH,
+                stars::GALAXY_STARS,
+                stars::GALAXY_GLOW
             )
         }
 
-        const BGS: [&'static str; 2] = [
+        const BGS: [&'static str; 4] = [
             r#"
 vec4 bg_col(vec3 dir) {
     return vec4(0.0, 0.0, 0.0, 1.0);
@@ -275,74 +536,397 @@ vec4 bg_col(vec3 dir) {
        at the texture border */
     vec4 res = textureGrad(bg_tex, tex_coords, dx, dy);
     return vec4(vec3(res) * BG_RAT, res.a);
+}"#,
+            r#"
+
+uniform samplerCube bg_cube;
+vec4 bg_col(vec3 dir) {
+    vec4 res = texture(bg_cube, dir);
+    return vec4(vec3(res) * BG_RAT, res.a);
+}"#,
+            r#"
+
+/* see stars.rs, the hashing here must match it exactly */
+uint hash(uint x) {
+    x = (x ^ (x >> 16)) * 0x7feb352du;
+    x = (x ^ (x >> 15)) * 0x846ca68bu;
+    return x ^ (x >> 16);
+}
+
+uint cell_hash(ivec3 c) {
+    uint h = hash(STAR_SEED);
+    h = hash(h + uint(c.x + 1024));
+    h = hash(h + uint(c.y + 1024));
+    return hash(h + uint(c.z + 1024));
+}
+
+float unit(inout uint h) {
+    h = hash(h);
+    return float((h >> 8) + 1u) / 16777216.0;
+}
+
+float band(vec3 dir) {
+    if (!GALAXY) {
+        return 0.0;
+    }
+    float lat = asin(clamp(dot(dir, GALAXY_NORMAL), -1.0, 1.0)) / GALAXY_WIDTH;
+    return exp(-lat * lat);
+}
+
+float lattice(ivec3 c) {
+    return float(cell_hash(c) >> 8) / 16777216.0;
+}
+
+float noise(vec3 p) {
+    ivec3 c = ivec3(floor(p));
+    vec3 f = p - floor(p);
+    f = f * f * (3.0 - 2.0 * f);
+
+    float x00 = mix(lattice(c), lattice(c + ivec3(1, 0, 0)), f.x);
+    float x10 = mix(lattice(c + ivec3(0, 1, 0)), lattice(c + ivec3(1, 1, 0)), f.x);
+    float x01 = mix(lattice(c + ivec3(0, 0, 1)), lattice(c + ivec3(1, 0, 1)), f.x);
+    float x11 = mix(lattice(c + ivec3(0, 1, 1)), lattice(c + ivec3(1, 1, 1)), f.x);
+    return mix(mix(x00, x10, f.y), mix(x01, x11, f.y), f.z);
+}
+
+vec3 star_col(float x) {
+    float i = clamp(x, 0.0, 1.0) * float(STAR_COL.length() - 1);
+    int lo = min(int(i), STAR_COL.length() - 2);
+    return mix(STAR_COL[lo], STAR_COL[lo + 1], i - float(lo));
+}
+
+vec4 bg_col(vec3 dir) {
+    /* traced directions drift away from unit length */
+    dir = normalize(dir);
+    vec3 p = dir * STAR_SHELL;
+    ivec3 c0 = ivec3(floor(p));
+
+    /* stars close enough to show up lie in the cells around p */
+    vec3 col = vec3(0.0);
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            for (int z = -1; z <= 1; z++) {
+                ivec3 cell = c0 + ivec3(x, y, z);
+                uint h = cell_hash(cell);
+                float present = unit(h);
+                float px = float(cell.x) + unit(h);
+                float py = float(cell.y) + unit(h);
+                float pz = float(cell.z) + unit(h);
+                vec3 pos = vec3(px, py, pz);
+
+                float r = length(pos);
+                vec3 star = pos / r;
+                float density = STAR_DENSITY * (1.0 + GALAXY_STARS * band(star));
+                if (abs(r - STAR_SHELL) >= 0.5 || present > density) {
+                    continue;
+                }
+
+                /* star counts grow as 10^(0.6 m), which puts brightness at
+                   the -2/3 power of a uniform variable */
+                float brightness = STAR_MIN_BRIGHTNESS * pow(unit(h), -2.0 / 3.0);
+                /* favour cool stars */
+                float t = unit(h);
+                vec3 tint = star_col(t * t);
+
+                float d = length(star - dir) / STAR_SIZE;
+                col += tint * brightness * exp(-0.5 * d * d);
+            }
+        }
+    }
+
+    if (GALAXY) {
+        float n = 0.65 * noise(dir * 5.0) + 0.35 * noise(dir * 13.0);
+        col += vec3(1.0, 0.9, 0.75) * (GALAXY_GLOW * band(dir) * (0.3 + 0.7 * n));
+    }
+    return vec4(col * BG_RAT, 1.0);
 }"#,
         ];
     }
 
     mod iter {
-        use clap::ArgMatches;
-        pub fn vars(args: &ArgMatches) -> String {
-            "".to_string()
+        use scene::SceneConfig;
+        pub fn vars(scene: &SceneConfig) -> String {
+            "int iterations = 0;".to_string()
         }
 
-        pub fn cond(args: &ArgMatches) -> String {
+        pub fn cond(scene: &SceneConfig) -> String {
             r#"float border = max(15.0 * 15.0, dot(src, src));
             while(dot(pos, pos) <= border &&
                 alpha_rem >= 0.01)"#
                 .to_string()
         }
+
+        pub fn step(scene: &SceneConfig) -> String {
+            "iterations++;".to_string()
+        }
+    }
+
+    /* auxiliary outputs, see the output module.  The checks record what
+       each ray hits whether or not they are written out. */
+    mod aov {
+        use output;
+        use scene::SceneConfig;
+
+        pub fn params(scene: &SceneConfig) -> String {
+            let outputs = if scene.aovs() {
+                r#"
+            /* hit, iterations, disk radius and shift */
+            layout(location = 1) out vec4 aov_ray;
+            /* escape direction and disk order */
+            layout(location = 2) out vec4 aov_escape;
+            /* drift of h and of the null constraint */
+            layout(location = 3) out vec4 aov_drift;
+            "#
+            } else {
+                ""
+            };
+            let heat = if scene.drift {
+                format!(
+                    r#"
+            const float DRIFT_MIN = {:?};
+
+            /* see output::drift_colour */
+            vec3 drift_colour(float drift) {{
+                float t = min(log(max(drift, DRIFT_MIN) / DRIFT_MIN) / log(1.0 / DRIFT_MIN), 1.0);
+                float r = clamp(3.0 * t - 1.0, 0.0, 1.0);
+                float g = clamp(3.0 * t - 2.0, 0.0, 1.0);
+                return vec3(r, g, clamp(3.0 * t, 0.0, 1.0) - r);
+            }}
+            "#,
+                    output::DRIFT_MIN
+                )
+            } else {
+                "".to_string()
+            };
+            format!(
+                r#"
+            const float HIT_ESCAPED = {:?};
+            const float HIT_HORIZON = {:?};
+            const float HIT_DISK = {:?};
+            {}
+            {}
+            "#,
+                output::HIT_ESCAPED,
+                output::HIT_HORIZON,
+                output::HIT_DISK,
+                outputs,
+                heat
+            )
+        }
+
+        pub fn vars(scene: &SceneConfig) -> String {
+            let drift = if scene.drift {
+                r#"
+            /* the invariants at the camera */
+            vec3 drift_h0 = cross(pos, dir);
+            float drift_v0 = dot(dir, dir);
+            float drift_null0 = drift_v0 - R_s * dot(drift_h0, drift_h0) / pow(dot(pos, pos), 1.5);
+            "#
+            } else {
+                ""
+            };
+            format!(
+                r#"
+            float hit = HIT_ESCAPED;
+            float disk_radius = 0.0;
+            /* crossings of the disk plane so far, and the order of the image
+               the first visible disk crossing is in */
+            int crossings = 0;
+            float disk_order = -1.0;
+            /* shifts of the disk crossings, weighted by their share of the
+               colour */
+            float shift_sum = 0.0;
+            float shift_weight = 0.0;
+            /* the largest drift from the invariants along the ray */
+            float drift_h = 0.0;
+            float drift_null = 0.0;
+            {}
+            "#,
+                drift
+            )
+        }
+
+        /* steps ending inside the horizon are left out, the capture ends
+           the ray there anyway */
+        pub fn step(scene: &SceneConfig) -> String {
+            if !scene.drift {
+                return "".to_string();
+            }
+            r#"
+            if(dot(pos, pos) > R_s * R_s) {
+                vec3 drift_h1 = cross(pos, dir);
+                float drift_null1 = dot(dir, dir) - R_s * dot(drift_h1, drift_h1) / pow(dot(pos, pos), 1.5);
+                drift_h = max(drift_h, length(drift_h1 - drift_h0) / max(length(drift_h0), R_s));
+                drift_null = max(drift_null, abs(drift_null1 - drift_null0) / drift_v0);
+            }
+            "#
+            .to_string()
+        }
+
+        pub fn write(scene: &SceneConfig) -> String {
+            let colour = if scene.drift {
+                "color = vec4(drift_colour(max(drift_h, drift_null)), 1.0) * weight;"
+            } else {
+                ""
+            };
+            let outputs = if scene.aovs() {
+                r#"
+            float shift = shift_weight > 0.0 ? shift_sum / shift_weight : 0.0;
+            aov_ray = vec4(hit, float(iterations), disk_radius, shift);
+            aov_escape = vec4(dot(pos, pos) > border ? normalize(dir) : vec3(0.0), disk_order);
+            aov_drift = vec4(drift_h, drift_null, 0.0, 0.0);
+            "#
+            } else {
+                ""
+            };
+            format!("{}{}", colour, outputs)
+        }
     }
 
     mod trace {
-        use clap::ArgMatches;
+        use lut;
+        use scene::{Method, SceneConfig};
 
         enum Type {
             Flat = 0,
             Verlet = 1,
             Rk4 = 2,
+            Kerr = 3,
+            Rk45 = 4,
+            Lut = 5,
         }
 
-        fn get_type(args: &ArgMatches) -> Type {
-            if args.is_present("flat") {
+        fn get_type(scene: &SceneConfig) -> Type {
+            if scene.flat {
                 Type::Flat
+            } else if scene.is_kerr() {
+                Type::Kerr
             } else {
-                match args.value_of("method").unwrap_or("rk4") {
-                    "verlet" => Type::Verlet,
-                    "rk4" => Type::Rk4,
-                    s => panic!("invalid integration scheme: {}", s),
+                match scene.method {
+                    Method::Flat => Type::Flat,
+                    Method::Verlet => Type::Verlet,
+                    Method::Rk4 => Type::Rk4,
+                    Method::Rk45 => Type::Rk45,
+                    Method::Lut => Type::Lut,
                 }
             }
         }
 
-        pub fn params(args: &ArgMatches) -> String {
-            PARAMS[get_type(args) as usize].to_string()
+        pub fn params(scene: &SceneConfig) -> String {
+            let t = get_type(scene);
+            let extra = match t {
+                Type::Rk45 => format!(
+                    r#"
+            const float RK45_TOL = {tol};
+            const float RK45_MIN_STEP = {min};
+            const float RK45_MAX_STEP = {max};
+            "#,
+                    tol = scene.tolerance,
+                    min = scene.min_step,
+                    max = scene.max_step
+                ),
+                Type::Lut => format!(
+                    r#"
+            const float LUT_WIDTH = {width:?};
+            const float LUT_HEIGHT = {height:?};
+            const float LUT_B_CRIT = {b_crit:?};
+            "#,
+                    width = lut::WIDTH as f32,
+                    height = lut::HEIGHT as f32,
+                    b_crit = lut::B_CRIT
+                ),
+                _ => "".to_string(),
+            };
+            format!("{}{}", extra, PARAMS[t as usize])
         }
 
-        pub fn update(args: &ArgMatches) -> String {
-            UPDATES[get_type(args) as usize].to_string()
+        pub fn update(scene: &SceneConfig) -> String {
+            UPDATES[get_type(scene) as usize].to_string()
         }
 
-        pub fn vars(args: &ArgMatches) -> String {
-            VARS[get_type(args) as usize].to_string()
+        pub fn vars(scene: &SceneConfig) -> String {
+            VARS[get_type(scene) as usize].to_string()
         }
 
-        const VARS: [&'static str; 3] = [
+        /* each variant also sets photon_l, the angular momentum about the
+           spin axis per unit energy of the photon arriving at the camera */
+        const VARS: [&'static str; 6] = [
+            r#"
+            float time_step;
+            float photon_l = -cross(pos, dir).y;
+            "#,
             r#"
             float time_step;
+            vec3 h = cross(pos, dir);
+            float h2 = dot(h, h);
+            float photon_l = -h.y / sqrt(max(1.0 - 2.0 * M * h2 / pow(length(pos), 3.0), 1e-6));
             "#,
             r#"
             float time_step;
             vec3 h = cross(pos, dir);
             float h2 = dot(h, h);
+            float photon_l = -h.y / sqrt(max(1.0 - 2.0 * M * h2 / pow(length(pos), 3.0), 1e-6));
             "#,
             r#"
             float time_step;
+            vec3 p = kerr_init(pos, dir);
+            float photon_l = pos.x * p.z - pos.z * p.x;
+            "#,
+            r#"
+            float time_step = clamp(TIME_STEP, RK45_MIN_STEP, RK45_MAX_STEP);
             vec3 h = cross(pos, dir);
             float h2 = dot(h, h);
+            float photon_l = -h.y / sqrt(max(1.0 - 2.0 * M * h2 / pow(length(pos), 3.0), 1e-6));
+            "#,
+            r#"
+            vec3 h = cross(pos, dir);
+            float h2 = dot(h, h);
+            float photon_l = -h.y / sqrt(max(1.0 - 2.0 * M * h2 / pow(length(pos), 3.0), 1e-6));
+
+            /* the plane of the orbit, in which the ray turns from lut_e1
+               towards lut_e2 */
+            float lut_r = length(pos);
+            vec3 lut_e1 = pos / lut_r;
+            vec3 lut_e2 = dir - dot(dir, lut_e1) * lut_e1;
+            if(dot(lut_e2, lut_e2) < 1e-12) {
+                /* a radial ray lies in any plane through it */
+                lut_e2 = cross(lut_e1, abs(lut_e1.x) < 0.9 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 1.0, 0.0));
+            }
+            lut_e2 = normalize(lut_e2);
+
+            /* the impact parameter, from h and the null constraint */
+            float lut_b = sqrt(h2 / max(dot(dir, dir) - R_s * h2 / (lut_r * lut_r * lut_r), 1e-12));
+            float lut_x = lut_coord(lut_b);
+            vec4 lut_col = lut_at(lut_x, 0.0);
+            float lut_u_end = lut_b < LUT_B_CRIT ? 1.0 / R_s : lut_col.a / lut_b;
+            float lut_phi_in = lut_at(lut_x, min(1.0 / (lut_r * lut_u_end), 1.0)).g * lut_col.b;
+            vec4 lut_orbit = vec4(lut_col.b, lut_u_end, lut_phi_in, dot(pos, dir) < 0.0 ? 1.0 : -1.0);
+
+            /* the angle turned when the ray escapes or falls in.  Rays above
+               the critical b inside the photon sphere are on orbits that
+               never escape, which the table doesn't hold, and fall in at
+               once. */
+            bool lut_bound = lut_b >= LUT_B_CRIT && lut_r < 3.0 * M;
+            bool lut_captured = lut_bound || (lut_orbit.w > 0.0 && lut_b < LUT_B_CRIT);
+            float lut_end;
+            if(lut_bound) {
+                lut_end = 0.0;
+            } else if(lut_orbit.w < 0.0) {
+                lut_end = lut_phi_in;
+            } else if(lut_b < LUT_B_CRIT) {
+                lut_end = lut_orbit.x - lut_phi_in;
+            } else {
+                lut_end = 2.0 * lut_orbit.x - lut_phi_in;
+            }
+
+            /* the first crossing of the disk plane, where the height of the
+               ray, r (cos(d) lut_e1.y + sin(d) lut_e2.y), is zero */
+            float lut_node = abs(lut_e1.y) + abs(lut_e2.y) > 0.0
+                ? mod(atan2(-lut_e1.y, lut_e2.y), M_PI) : 1e30;
             "#,
         ];
 
-        const PARAMS: [&'static str; 3] = [
+        const PARAMS: [&'static str; 6] = [
             r#"
             uniform float TIME_STEP;
         "#,
@@ -355,10 +939,141 @@ vec4 bg_col(vec3 dir) {
             vec3 accel(vec3 pos, float h2) {
                 return -pos * 1.5 * h2 * pow(dot(pos, pos), -2.5);
             }
+        "#,
+            r#"
+            uniform float TIME_STEP;
+
+            /* Hamilton's equations in Kerr-Schild coordinates, see kerr::deriv.
+               The ray is traced backwards in time, which is the same as
+               tracing it forwards around a hole spinning the other way. */
+            void kerr_deriv(vec3 x, vec3 p, out vec3 dx, out vec3 dp) {
+                float a = -A_SPIN;
+                float a2 = a * a;
+                float r = ks_r(x);
+                float r2 = r * r;
+                float sig = r2 * r2 + a2 * x.y * x.y;
+                float A = r2 + a2;
+                vec3 l = vec3((r * x.x - a * x.z) / A, x.y / r, (r * x.z + a * x.x) / A);
+                float f = 2.0 * M * r2 * r / sig;
+                float q = 1.0 + dot(l, p);
+
+                dx = p - f * q * l;
+
+                vec3 dr = r * (r2 * x + vec3(0.0, a2 * x.y, 0.0)) / sig;
+                vec3 df = 2.0 * M * r2 * ((3.0 * a2 * x.y * x.y - r2 * r2) * dr
+                    - vec3(0.0, 2.0 * a2 * r * x.y, 0.0)) / (sig * sig);
+                float n = r * (x.x * p.x + x.z * p.z) + a * (x.x * p.z - x.z * p.x);
+                vec3 dn = dr * (x.x * p.x + x.z * p.z) + vec3(r * p.x + a * p.z, 0.0, r * p.z - a * p.x);
+                vec3 dlp = dn / A - n * 2.0 * r * dr / (A * A) + vec3(0.0, p.y / r, 0.0)
+                    - x.y * p.y * dr / r2;
+                dp = 0.5 * q * q * df + f * q * dlp;
+            }
+
+            vec3 kerr_init(vec3 pos, vec3 dir) {
+                float a = -A_SPIN;
+                float r = ks_r(pos);
+                float r2 = r * r;
+                float A = r2 + a * a;
+                vec3 l = vec3((r * pos.x - a * pos.z) / A, pos.y / r, (r * pos.z + a * pos.x) / A);
+                float f = 2.0 * M * r2 * r / (r2 * r2 + a * a * pos.y * pos.y);
+                float s = dot(l, dir);
+
+                /* solve g(v, v) = 0 for the future pointing dt/dl */
+                float vt = (f * s + sqrt(1.0 - f + f * s * s)) / (1.0 - f);
+                float energy = vt - f * (vt + s);
+                return (dir + f * (vt + s) * l) / energy;
+            }
+        "#,
+            r#"
+            uniform float TIME_STEP;
+
+            vec3 accel(vec3 pos, float h2) {
+                return -pos * 1.5 * h2 * pow(dot(pos, pos), -2.5);
+            }
+
+            /* one Dormand-Prince step of length ts, returns the error estimate */
+            float rk45_step(vec3 pos, vec3 dir, float h2, float ts, out vec3 npos, out vec3 ndir) {
+                vec3 x1 = dir;
+                vec3 v1 = accel(pos, h2);
+
+                vec3 x2 = dir + ts * (1.0/5.0) * v1;
+                vec3 v2 = accel(pos + ts * (1.0/5.0) * x1, h2);
+
+                vec3 x3 = dir + ts * ((3.0/40.0) * v1 + (9.0/40.0) * v2);
+                vec3 v3 = accel(pos + ts * ((3.0/40.0) * x1 + (9.0/40.0) * x2), h2);
+
+                vec3 x4 = dir + ts * ((44.0/45.0) * v1 - (56.0/15.0) * v2 + (32.0/9.0) * v3);
+                vec3 v4 = accel(pos + ts * ((44.0/45.0) * x1 - (56.0/15.0) * x2 + (32.0/9.0) * x3), h2);
+
+                vec3 x5 = dir + ts * ((19372.0/6561.0) * v1 - (25360.0/2187.0) * v2
+                    + (64448.0/6561.0) * v3 - (212.0/729.0) * v4);
+                vec3 v5 = accel(pos + ts * ((19372.0/6561.0) * x1 - (25360.0/2187.0) * x2
+                    + (64448.0/6561.0) * x3 - (212.0/729.0) * x4), h2);
+
+                vec3 x6 = dir + ts * ((9017.0/3168.0) * v1 - (355.0/33.0) * v2
+                    + (46732.0/5247.0) * v3 + (49.0/176.0) * v4 - (5103.0/18656.0) * v5);
+                vec3 v6 = accel(pos + ts * ((9017.0/3168.0) * x1 - (355.0/33.0) * x2
+                    + (46732.0/5247.0) * x3 + (49.0/176.0) * x4 - (5103.0/18656.0) * x5), h2);
+
+                npos = pos + ts * ((35.0/384.0) * x1 + (500.0/1113.0) * x3 + (125.0/192.0) * x4
+                    - (2187.0/6784.0) * x5 + (11.0/84.0) * x6);
+                ndir = dir + ts * ((35.0/384.0) * v1 + (500.0/1113.0) * v3 + (125.0/192.0) * v4
+                    - (2187.0/6784.0) * v5 + (11.0/84.0) * v6);
+
+                vec3 x7 = ndir;
+                vec3 v7 = accel(npos, h2);
+
+                /* difference between the 5th and embedded 4th order solutions */
+                vec3 ex = ts * ((71.0/57600.0) * x1 - (71.0/16695.0) * x3 + (71.0/1920.0) * x4
+                    - (17253.0/339200.0) * x5 + (22.0/525.0) * x6 - (1.0/40.0) * x7);
+                vec3 ev = ts * ((71.0/57600.0) * v1 - (71.0/16695.0) * v3 + (71.0/1920.0) * v4
+                    - (17253.0/339200.0) * v5 + (22.0/525.0) * v6 - (1.0/40.0) * v7);
+                return max(length(ex), length(ev));
+            }
+
+            /* factor to scale the step by to bring the error to RK45_TOL */
+            float rk45_factor(float err) {
+                return clamp(0.9 * pow(RK45_TOL / max(err, 1e-10), 0.2), 0.2, 5.0);
+            }
+        "#,
+            r#"
+            uniform float TIME_STEP;
+            /* the incoming halves of the orbits, see lut::Table */
+            uniform sampler2D lut;
+
+            /* the column of impact parameter b, see lut::coord */
+            float lut_coord(float b) {
+                float edge = 0.5 / LUT_WIDTH;
+                if(b < LUT_B_CRIT) {
+                    float w = sqrt(1.0 - b / LUT_B_CRIT);
+                    return min((1.0 - w) / 2.0, 0.5 - edge);
+                }
+                float w = sqrt(1.0 - LUT_B_CRIT / b);
+                return max((1.0 + w) / 2.0, 0.5 + edge);
+            }
+
+            /* the table a fraction t along the orbit in column x */
+            vec4 lut_at(float x, float t) {
+                return texture(lut, vec2(x, (t * (LUT_HEIGHT - 1.0) + 0.5) / LUT_HEIGHT));
+            }
+
+            /* the radius of a ray once it has turned by d, on the orbit in
+               column x.  orbit holds the angle and u the incoming half ends
+               at, the angle into it the ray started at, and whether it moves
+               along it (1) or back out (-1). */
+            float lut_radius(float x, vec4 orbit, float d) {
+                float phi = orbit.z + orbit.w * d;
+                /* the outgoing half mirrors the incoming one */
+                phi = phi > orbit.x ? 2.0 * orbit.x - phi : phi;
+                return 1.0 / (lut_at(x, clamp(phi / orbit.x, 0.0, 1.0)).r * orbit.y);
+            }
         "#,
         ];
 
-        const UPDATES: [&'static str; 3] = [
+        /* the lut update jumps from one crossing of the disk plane to the
+           next and then to the end of the orbit, making each a short step
+           that bh::check and ad::check see as the integrators' steps */
+        const UPDATES: [&'static str; 6] = [
             r#"
             time_step = ts_func(TIME_STEP, pos);
             npos = pos + dir * time_step;
@@ -404,13 +1119,97 @@ vec4 bg_col(vec3 dir) {
                 h = cross(ndir, npos);
                 h2 = dot(h, h);
             }
+        "#,
+            r#"
+            time_step = ts_func(TIME_STEP, pos);
+            {
+                vec3 k1x, k1p, k2x, k2p, k3x, k3p, k4x, k4p;
+                kerr_deriv(pos, p, k1x, k1p);
+                kerr_deriv(pos + 0.5 * time_step * k1x, p + 0.5 * time_step * k1p, k2x, k2p);
+                kerr_deriv(pos + 0.5 * time_step * k2x, p + 0.5 * time_step * k2p, k3x, k3p);
+                kerr_deriv(pos + time_step * k3x, p + time_step * k3p, k4x, k4p);
+
+                vec3 vel = k1x + 2*k2x + 2*k3x + k4x;
+                npos = pos + (time_step/6.0) * vel;
+                p = p + (time_step/6.0) * (k1p + 2*k2p + 2*k3p + k4p);
+                ndir = normalize(vel);
+            }
+        "#,
+            r#"
+            {
+                float err;
+                for(int attempt = 0; attempt < 8; attempt++) {
+                    err = rk45_step(pos, dir, h2, time_step, npos, ndir);
+                    if(err <= RK45_TOL || time_step <= RK45_MIN_STEP) {
+                        break;
+                    }
+                    time_step = max(time_step * rk45_factor(err), RK45_MIN_STEP);
+                }
+                /* the next step starts from the size this one suggests */
+                time_step = clamp(time_step * rk45_factor(err), RK45_MIN_STEP, RK45_MAX_STEP);
+            }
+            if(dot(ndir, ndir) > 100.0) {
+                /* experimental renormalization */
+                ndir = normalize(ndir);
+                h = cross(ndir, npos);
+                h2 = dot(h, h);
+            }
+        "#,
+            r#"
+            {
+                /* crossings beyond the border are on the way out, after the
+                   integrators would have stopped, and have none after them */
+                bool crossing = lut_node < lut_end;
+                float r = crossing ? lut_radius(lut_x, lut_orbit, lut_node) : 0.0;
+                if(crossing && r * r < border) {
+                    /* across the disk plane where the ray crosses it, keeping
+                       to its radius */
+                    vec3 p = r * (cos(lut_node) * lut_e1 + sin(lut_node) * lut_e2);
+                    pos = r * normalize(p + vec3(0.0, 1e-3 * r, 0.0));
+                    npos = r * normalize(p - vec3(0.0, 1e-3 * r, 0.0));
+                    ndir = dir;
+                    lut_node += M_PI;
+                } else if(lut_captured) {
+                    /* into the horizon where the ray falls in */
+                    vec3 p = R_s * (cos(lut_end) * lut_e1 + sin(lut_end) * lut_e2);
+                    pos = 1.001 * p;
+                    npos = 0.999 * p;
+                    ndir = dir;
+                } else {
+                    /* out past the border, along the direction the ray leaves in */
+                    ndir = cos(lut_end) * lut_e1 + sin(lut_end) * lut_e2;
+                    pos = sqrt(border) * ndir;
+                    npos = 2.0 * pos;
+                }
+            }
         "#,
         ];
     }
 
     mod bh {
-        use clap::ArgMatches;
-        pub fn check(args: &ArgMatches) -> String {
+        use kerr;
+        use scene::{SceneConfig, Surface};
+
+        pub fn params(scene: &SceneConfig) -> String {
+            let spin = scene.spin();
+            format!(
+                r#"
+                const float A_SPIN = {a};
+                const float R_H = {r_h};
+
+                /* Kerr-Schild radius, see kerr::ks_r */
+                float ks_r(vec3 p) {{
+                    float a2 = A_SPIN * A_SPIN;
+                    float rho2 = dot(p, p) - a2;
+                    return sqrt(0.5 * (rho2 + sqrt(rho2 * rho2 + 4.0 * a2 * p.y * p.y)));
+                }}
+                "#,
+                a = kerr::spin_length(spin),
+                r_h = kerr::horizon(spin)
+            )
+        }
+
+        pub fn check(scene: &SceneConfig) -> String {
             format!(r#"
             {{
             float mindist2;
@@ -419,16 +1218,17 @@ vec4 bg_col(vec3 dir) {
 
             {get_closest}
 
-            if(dot(closest, closest) <= R_s * R_s) {{
+            if(ks_r(closest) <= R_H) {{
                 vec4 colour;
                 {colour}
+                hit = HIT_HORIZON;
                 ccolor += colour * alpha_rem * 1.0;
                 alpha_rem -= alpha_rem * 1.0;
             }}
             }}
             "#,
                 get_closest = GET_CLOSEST,
-                colour = get_colour(args))
+                colour = get_colour(scene))
         }
 
         const GET_CLOSEST: &'static str = r#"
@@ -443,10 +1243,10 @@ vec4 bg_col(vec3 dir) {
             }
         "#;
 
-        fn get_colour(args: &ArgMatches) -> &'static str {
-            match args.value_of("surface").unwrap() {
-                "black" => "colour = vec4(0.0, 0.0, 0.0, 1.0);",
-                "checkered" => r#"
+        fn get_colour(scene: &SceneConfig) -> &'static str {
+            match scene.surface {
+                Surface::Black => "colour = vec4(0.0, 0.0, 0.0, 1.0);",
+                Surface::Checkered => r#"
                     const float PI = 3.1415926535897932384626433832795;
                     float yaw = atan2(closest.y, closest.x);
                     float pitch = atan(sqrt(
@@ -456,50 +1256,144 @@ vec4 bg_col(vec3 dir) {
                     int b1 = int(pitch * 180 / PI / 15);
                     int red = (b0 + b1) % 2;
                     colour = vec4(red, 0.0, 0.0, 1.0);
-                "#
-                ,
-                _ => panic!("Invalid blackhole surface")
+                "#,
             }
         }
     }
 
     mod ad {
-        use clap::ArgMatches;
+        use blackbody;
+        use scene::{Disk, SceneConfig};
+
         enum Type {
             NoDisk = 0,
             White = 1,
             Tex = 2,
             Dynamic = 3,
+            Blackbody = 4,
         }
 
-        fn get_type(args: &ArgMatches) -> Type {
-            match args.value_of("accdisk").unwrap() {
-                "none" => Type::NoDisk,
-                "white" => Type::White,
-                "img" => Type::Tex,
-                "dyno" => Type::Dynamic,
-                s => panic!("invalid accretion disk type: {}", s),
+        fn get_type(scene: &SceneConfig) -> Type {
+            match scene.disk {
+                Disk::None => Type::NoDisk,
+                Disk::White => Type::White,
+                Disk::Img => Type::Tex,
+                Disk::Dyno => Type::Dynamic,
+                Disk::Blackbody => Type::Blackbody,
             }
         }
 
-        pub fn check(args: &ArgMatches) -> String {
+        pub fn check(scene: &SceneConfig) -> String {
             CHECK.to_string()
         }
 
-        pub fn params(args: &ArgMatches) -> String {
-            let or: f32 = args.value_of("oradius").unwrap().parse().unwrap();
-            let ir: f32 = args.value_of("iradius").unwrap().parse().unwrap();
-            let extra = PARAMS[get_type(args) as usize].to_string();
+        pub fn params(scene: &SceneConfig) -> String {
+            let ty = get_type(scene);
+            let mut extra = String::new();
+            if let Type::Blackbody = ty {
+                extra += &bb_tables(scene);
+            }
+            extra += PARAMS[ty as usize];
+            let weights: Vec<String> = scene.order_weights.iter().map(|w| format!("{:?}", w)).collect();
             format!(
                 r#"
                 const float DISK_O_RAD = {};
                 const float DISK_I_RAD = {};
+                const bool SHIFT_GRAV = {};
+                const bool SHIFT_DOPPLER = {};
+                const float ORDER_WEIGHTS[{n}] = float[]({weights});
+
+                /* opacity of the disk where a ray crosses its plane after n
+                   earlier crossings, see SceneConfig::order_weights */
+                float order_weight(int n) {{
+                    return ORDER_WEIGHTS[min(n, {n} - 1)];
+                }}
+                {}
                 {}
                 "#,
-                or, ir, extra
+                scene.outer_radius,
+                scene.inner_radius(),
+                scene.redshift,
+                scene.doppler,
+                SHIFT,
+                extra,
+                n = weights.len(),
+                weights = weights.join(", ")
+            )
+        }
+
+        fn bb_tables(scene: &SceneConfig) -> String {
+            let disk = blackbody::Disk::new(scene);
+            let temps: Vec<String> = disk.temps.iter().map(|t| format!("{:e}", t)).collect();
+            let cols: Vec<String> = disk
+                .colours
+                .iter()
+                .map(|c| format!("vec3({:e}, {:e}, {:e})", c[0], c[1], c[2]))
+                .collect();
+            format!(
+                r#"
+                const float BB_LOG_T_MIN = {:e};
+                const float BB_LOG_T_MAX = {:e};
+                const float DISK_TEMP[{}] = float[]({});
+                const vec3 BB_COL[{}] = vec3[]({});
+                "#,
+                blackbody::LOG_T_MIN,
+                blackbody::LOG_T_MAX,
+                temps.len(),
+                temps.join(", "),
+                cols.len(),
+                cols.join(", ")
             )
         }
 
+        const SHIFT: &'static str = r#"
+            /* nu_obs / nu_emit for light from disk matter orbiting at radius
+               r, split into gravitational and doppler parts, see
+               kerr::disk_shift */
+            vec2 disk_shift(float r, float l) {
+                float a = A_SPIN;
+                float sqm = sqrt(M);
+                float r15 = pow(r, 1.5);
+                float omega = sqm / (r15 + a * sqm);
+                float ut = (r15 + a * sqm) /
+                    (pow(r, 0.75) * sqrt(max(r15 - 3.0 * M * sqrt(r) + 2.0 * a * sqm, 1e-6)));
+
+                float delta = r * r - 2.0 * M * r + a * a;
+                float A = (r * r + a * a) * (r * r + a * a) - a * a * delta;
+                float alpha = sqrt(max(delta * r * r / A, 0.0));
+                float zamo = 2.0 * M * a * r / A;
+
+                float r_o = ks_r(src);
+                float f_o = 2.0 * M * r_o * r_o * r_o / (r_o * r_o * r_o * r_o + a * a * src.y * src.y);
+
+                float g_grav = alpha / (sqrt(1.0 - f_o) * (1.0 - zamo * l));
+                float g_dopp = (1.0 - zamo * l) / (alpha * ut * (1.0 - omega * l));
+                return vec2(g_grav, g_dopp);
+            }
+
+            float disk_g(float r, float l) {
+                vec2 g = disk_shift(r, l);
+                return (SHIFT_GRAV ? g.x : 1.0) * (SHIFT_DOPPLER ? g.y : 1.0);
+            }
+
+            /* colour change of a 6500K blackbody seen shifted by g, with
+               green kept fixed */
+            vec3 bb_tint(float g) {
+                const vec3 LAMBDA = vec3(610e-9, 550e-9, 465e-9);
+                const float T0 = 6500.0;
+                g = max(g, 0.1);
+                /* ratio of Planck's law at T0 * g and T0, hc / k = 0.014388 m K */
+                vec3 t = (exp(0.014388 / (LAMBDA * T0)) - 1.0) / (exp(0.014388 / (LAMBDA * T0 * g)) - 1.0);
+                return t / t.g;
+            }
+
+            /* applies a frequency shift to a disk colour, moving its colour
+               temperature and scaling its intensity by g^4 */
+            vec4 shift_col(vec4 col, float g) {
+                return vec4(vec3(col) * bb_tint(g) * pow(g, 4.0), col.a);
+            }
+        "#;
+
         const CHECK: &'static str = r#"
             {
             float t = -pos.y / (npos.y - pos.y);
@@ -507,29 +1401,40 @@ vec4 bg_col(vec3 dir) {
                 vec3 p = pos + t * (npos - pos);
                 float mag = length(p);
                 if(mag >= DISK_I_RAD && mag <= DISK_O_RAD) {
-                    vec4 col = ad_col(p, mag);
-                    float rat = col.a;
+                    float g = disk_g(ks_r(p), photon_l);
+                    vec4 col = ad_col(p, mag, g);
+                    float rat = col.a * order_weight(crossings);
+                    if(rat > 0.0) {
+                        if(hit == HIT_ESCAPED) {
+                            hit = HIT_DISK;
+                            disk_radius = ks_r(p);
+                            disk_order = float(crossings);
+                        }
+                        shift_sum += g * alpha_rem * rat;
+                        shift_weight += alpha_rem * rat;
+                    }
                     ccolor += col * alpha_rem * rat;
                     alpha_rem -= alpha_rem * rat;
                 }
+                crossings++;
             }
             }
         "#;
 
-        const PARAMS: [&'static str; 4] = [
+        const PARAMS: [&'static str; 5] = [
             r#"
-            vec4 ad_col(vec3 intersect, float mag) {
+            vec4 ad_col(vec3 intersect, float mag, float g) {
                 return vec4(0.0, 0.0, 0.0, 0.0);
             }
         "#,
             r#"
-            vec4 ad_col(vec3 intersect, float mag) {
-                return vec4(1.0, 1.0, 1.0, 1.0);
+            vec4 ad_col(vec3 intersect, float mag, float g) {
+                return shift_col(vec4(1.0, 1.0, 1.0, 1.0), g);
             }
         "#,
             r#"
             uniform sampler2D ad_tex;
-            vec4 ad_col(vec3 intersect, float mag) {
+            vec4 ad_col(vec3 intersect, float mag, float g) {
                 float x = yaw_coord(intersect);
                 float y = (DISK_O_RAD - mag) / (DISK_O_RAD - DISK_I_RAD);
 
@@ -548,7 +1453,7 @@ vec4 bg_col(vec3 dir) {
                 vec2 dx = dot(dx1, dx1) < dot(dx2, dx2) ? dx1 : dx2;
                 vec2 dy = dot(dy1, dy1) < dot(dy2, dy2) ? dy1 : dy2;
 
-                return textureGrad(ad_tex, c1, dx, dy);
+                return shift_col(textureGrad(ad_tex, c1, dx, dy), g);
                 vec3 col = vec3(textureGrad(ad_tex, c1, dx, dy));
                 float alpha = clamp(dot(col, col)/3.0, 0.0, 1.0);
                 return vec4(col, alpha);
@@ -582,7 +1487,7 @@ vec4 bg_col(vec3 dir) {
                 return val * 10;
             }
 
-            vec4 ad_col(vec3 intersect, float mag) {
+            vec4 ad_col(vec3 intersect, float mag, float g) {
                 float phi_prime = yaw(intersect);
                 //float val1 = integrate(phi_prime, mag);
 
@@ -591,7 +1496,11 @@ vec4 bg_col(vec3 dir) {
                 float y = (DISK_O_RAD - mag) / (DISK_O_RAD - DISK_I_RAD);
                 float val = texture(ad_tex, vec2(x, y)).r * 10;
 
-                return vec4(val, clamp(val * 2 - 1.0, 0.0, 1.0), clamp(val * 3 - 2.0, 0.0, 1.0), exp(-mag / 2) * val);
+                /* val doubles as the temperature, so the shifted colour comes
+                   from val * g while the brightness scales with g^4 */
+                float t = val * g;
+                vec3 ramp = vec3(t, clamp(t * 2 - 1.0, 0.0, 1.0), clamp(t * 3 - 2.0, 0.0, 1.0));
+                return vec4(ramp / max(t, 1e-6) * val * pow(g, 4.0), exp(-mag / 2) * val);
             }
 
             vec4 ad_col1(vec3 intersect, float mag) {
@@ -621,7 +1530,196 @@ vec4 bg_col(vec3 dir) {
                 float alpha = clamp(dot(col, col)/3.0, 0.0, 1.0);
                 return vec4(col, alpha);
             }
+        "#,
+            r#"
+            /* the disk temperature, tabulated evenly in radius, see
+               blackbody::Disk */
+            float disk_temp(float mag) {
+                float x = clamp((mag - DISK_I_RAD) / (DISK_O_RAD - DISK_I_RAD), 0.0, 1.0)
+                    * (DISK_TEMP.length() - 1);
+                int i = min(int(x), DISK_TEMP.length() - 2);
+                return mix(DISK_TEMP[i], DISK_TEMP[i + 1], x - i);
+            }
+
+            /* linear colour of a blackbody, tabulated evenly in log10(t) */
+            vec3 bb_col(float t) {
+                if(t <= 0.0) {
+                    return vec3(0.0);
+                }
+                float x = clamp((log2(t) / log2(10.0) - BB_LOG_T_MIN) / (BB_LOG_T_MAX - BB_LOG_T_MIN), 0.0, 1.0)
+                    * (BB_COL.length() - 1);
+                int i = min(int(x), BB_COL.length() - 2);
+                return mix(BB_COL[i], BB_COL[i + 1], x - i);
+            }
+
+            /* the shifted spectrum of a blackbody is a blackbody at g times
+               its temperature, so g needs no separate intensity factor */
+            vec4 ad_col(vec3 intersect, float mag, float g) {
+                return vec4(bb_col(disk_temp(mag) * g), 1.0);
+            }
         "#,
         ];
     }
 }
+
+mod tone_shader {
+    use super::section;
+    use scene::{SceneConfig, ToneMap};
+
+    pub fn gen_shader(scene: &SceneConfig) -> String {
+        format!(
+            r#"
+#version 330
+
+uniform sampler2D hdr;
+
+layout(location = 0) out vec4 color;
+
+{glare_func}
+
+{tone_func}
+
+void main() {{
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    vec4 c = texelFetch(hdr, p, 0);
+    color = vec4(tone_map((max(c.rgb, 0.0) + glare(p)) * EXPOSURE), c.a);
+}}
+    "#,
+            glare_func = section("bloom", glare(scene)),
+            tone_func = section("tone", func(scene))
+        )
+    }
+
+    /// The blurred highlights added to the pixel at `p`.
+    fn glare(scene: &SceneConfig) -> String {
+        if scene.bloom > 0.0 {
+            format!(
+                r#"
+            const float BLOOM = {:?};
+            uniform sampler2D bloom;
+
+            vec3 glare(ivec2 p) {{
+                return BLOOM * texelFetch(bloom, p, 0).rgb;
+            }}
+            "#,
+                scene.bloom
+            )
+        } else {
+            r#"
+            vec3 glare(ivec2 p) {
+                return vec3(0.0);
+            }
+            "#
+            .to_string()
+        }
+    }
+
+    enum Type {
+        Clamp = 0,
+        Reinhard = 1,
+        Aces = 2,
+        Filmic = 3,
+    }
+
+    fn get_type(scene: &SceneConfig) -> Type {
+        match scene.tone_map {
+            ToneMap::Clamp => Type::Clamp,
+            ToneMap::Reinhard => Type::Reinhard,
+            ToneMap::Aces => Type::Aces,
+            ToneMap::Filmic => Type::Filmic,
+        }
+    }
+
+    fn func(scene: &SceneConfig) -> String {
+        format!(
+            r#"
+            const float EXPOSURE = {};
+            {}
+            "#,
+            2.0f32.powf(scene.exposure),
+            TONE_MAPS[get_type(scene) as usize]
+        )
+    }
+
+    /* the output target clamps what these return to [0, 1] */
+    const TONE_MAPS: [&'static str; 4] = [
+        r#"
+            vec3 tone_map(vec3 c) {
+                return c;
+            }
+        "#,
+        r#"
+            vec3 tone_map(vec3 c) {
+                return c / (1.0 + c);
+            }
+        "#,
+        r#"
+            vec3 tone_map(vec3 c) {
+                return c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14);
+            }
+        "#,
+        r#"
+            vec3 hable(vec3 x) {
+                const float A = 0.15, B = 0.50, C = 0.10, D = 0.20, E = 0.02, F = 0.30;
+                return (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F;
+            }
+
+            /* with an exposure bias of 2, mapping 11.2 to white */
+            vec3 tone_map(vec3 c) {
+                return hable(2.0 * c) / hable(vec3(11.2));
+            }
+        "#,
+    ];
+}
+
+mod bloom_shader {
+    use super::section;
+    use scene::SceneConfig;
+
+    /* one pass blurs along `axis` what exceeds `threshold`, within the
+     * pixels `bounds.xy` up to `bounds.zw` of a single eye */
+    #[allow(unused_variables)]
+    pub fn gen_shader(scene: &SceneConfig) -> String {
+        format!(
+            r#"
+#version 330
+
+uniform sampler2D src;
+uniform ivec2 axis;
+uniform ivec4 bounds;
+uniform float threshold;
+uniform float sigma;
+uniform int reach;
+
+layout(location = 0) out vec4 color;
+
+{bright_func}
+
+void main() {{
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    vec3 sum = vec3(0.0);
+    float weights = 0.0;
+    for (int i = -reach; i <= reach; i++) {{
+        ivec2 q = p + i * axis;
+        if (any(lessThan(q, bounds.xy)) || any(greaterThanEqual(q, bounds.zw))) {{
+            continue;
+        }}
+        float w = exp(-float(i * i) / (2.0 * sigma * sigma));
+        sum += w * bright(texelFetch(src, q, 0).rgb);
+        weights += w;
+    }}
+    color = vec4(sum / weights, 1.0);
+}}
+    "#,
+            bright_func = section("bloom", BRIGHT.to_string())
+        )
+    }
+
+    /* see `bloom::bright` */
+    const BRIGHT: &'static str = r#"
+            vec3 bright(vec3 c) {
+                float m = max(max(c.r, c.g), c.b);
+                return m > threshold ? c * (1.0 - threshold / m) : vec3(0.0);
+            }
+        "#;
+}
@@ -0,0 +1,103 @@
+//! The procedural `stars` background, which needs nothing from disk.  Point
+//! sources make lensed images far easier to read than a photo does.
+//!
+//! Stars sit in the cells of an integer lattice, at most one per cell, and
+//! only those in a shell of radius `SHELL` cells around the origin are
+//! kept, so each direction sees a spherical layer of them.  Everything
+//! about a star comes from hashing its cell with the seed, using integer
+//! arithmetic so that the shader generator and the CPU tracer draw exactly
+//! the same sky.
+
+use cgmath::{InnerSpace, Vector3};
+
+use std::f32::consts::PI;
+
+use blackbody;
+use scene::{Projection, SceneConfig};
+
+/// Radius of the shell of stars, in lattice cells.
+pub const SHELL: f32 = 128.0;
+/// Chance that a cell holds a star, away from the galactic band.
+pub const DENSITY: f32 = 0.2;
+/// Peak brightness of the faintest stars.
+pub const MIN_BRIGHTNESS: f32 = 0.03;
+/// How many times denser the stars are in the plane of the galaxy.
+pub const GALAXY_STARS: f32 = 3.0;
+/// Brightness of the diffuse glow in the plane of the galaxy.
+pub const GALAXY_GLOW: f32 = 0.04;
+/// Angular half-width of the galactic band, in radians.
+pub const GALAXY_WIDTH: f32 = 0.2;
+/// Number of temperatures the star colours are tabulated at.
+pub const COL_SAMPLES: usize = 16;
+/// Star temperatures run from `T_MIN` to `T_MAX` kelvin.
+pub const T_MIN: f32 = 2500.0;
+pub const T_MAX: f32 = 30000.0;
+
+pub struct Starfield {
+    pub seed: u32,
+    /// Normal of the galactic plane, or `None` for a uniform sky.
+    pub galaxy: Option<Vector3<f32>>,
+    /// Angular width, in radians, of a star's Gaussian profile.  Stars are
+    /// drawn about a pixel wide, but no wider than the cells searched
+    /// around a direction allow.
+    pub size: f32,
+    /// Colour of unit luminance at temperatures evenly spaced in `log(T)`.
+    pub colours: Vec<[f32; 3]>,
+}
+
+impl Starfield {
+    pub fn new(scene: &SceneConfig) -> Self {
+        let pixel = match scene.projection {
+            Projection::Pinhole => 2.0 * (scene.fov / 2.0 / 180.0 * PI).tan(),
+            Projection::Equirect => 2.0 * PI,
+        } / scene.eye_dims().0 as f32;
+        /* stars up to 3 widths away must lie in the neighbouring cells */
+        let size = (0.6 * pixel).min(0.8 / (3.0 * SHELL));
+
+        let colours = (0..COL_SAMPLES)
+            .map(|i| {
+                let x = i as f32 / (COL_SAMPLES - 1) as f32;
+                blackbody::chromaticity(temperature(x) as f64)
+            })
+            .collect();
+
+        Starfield {
+            seed: scene.seed,
+            galaxy: if scene.galaxy {
+                Some(Vector3::new(0.3, 0.9, -0.3).normalize())
+            } else {
+                None
+            },
+            size: size,
+            colours: colours,
+        }
+    }
+}
+
+/// The temperature at `x` between 0 and 1 along the colour table.
+pub fn temperature(x: f32) -> f32 {
+    T_MIN * (T_MAX / T_MIN).powf(x)
+}
+
+/// The lowbias32 integer hash from Chris Wellons' hash prospector.
+pub fn hash(x: u32) -> u32 {
+    let x = (x ^ (x >> 16)).wrapping_mul(0x7feb_352d);
+    let x = (x ^ (x >> 15)).wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+/// The hash of a lattice cell, which seeds the draws for its star.
+pub fn cell_hash(seed: u32, cell: Vector3<i32>) -> u32 {
+    /* keep the coordinates positive, GLSL leaves negative ints cast to
+     * uint undefined */
+    let h = hash(seed);
+    let h = hash(h.wrapping_add((cell.x + 1024) as u32));
+    let h = hash(h.wrapping_add((cell.y + 1024) as u32));
+    hash(h.wrapping_add((cell.z + 1024) as u32))
+}
+
+/// Advances `h` and turns it into a float in (0, 1].
+pub fn unit(h: &mut u32) -> f32 {
+    *h = hash(*h);
+    ((*h >> 8) + 1) as f32 / 16777216.0
+}
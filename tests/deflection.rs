@@ -0,0 +1,193 @@
+//! Checks the geodesic integrators of the CPU tracer against the known
+//! bending of light by a Schwarzschild hole.
+//!
+//! Rays are shot past the hole from far away at a range of impact parameters
+//! `b`, and the angle between the directions they arrive and leave in is
+//! compared with the exact deflection, which Darwin gave as an elliptic
+//! integral, and with the weak field limit `4M / b`.  Rays with `b` below
+//! the critical `3√3 M` must instead fall into the hole.
+
+extern crate cgmath;
+extern crate gr_trace;
+
+use cgmath::vec3;
+
+use std::f64::consts::PI;
+
+use gr_trace::output::{HIT_ESCAPED, HIT_HORIZON};
+use gr_trace::scene::{Background, Disk, Method};
+use gr_trace::{Scene, Tracer};
+
+/// The hole's mass in the tracer's units, where the Schwarzschild radius is
+/// 1.
+const M: f64 = 0.5;
+
+/// How far from the hole rays start and end.  The bending left beyond this
+/// is about `2Mb / R^2`, well below the tolerances for the `b` used.
+const R: f32 = 10000.0;
+
+#[test]
+fn rk4_matches_exact_deflection() {
+    let tracer = tracer(Method::Rk4, 0.64);
+    for &b in &[2.8, 3.0, 4.0, 6.0, 10.0, 20.0] {
+        check(&tracer, b, exact(b), 0.01, "exact");
+    }
+}
+
+#[test]
+fn rk45_matches_exact_deflection() {
+    let tracer = tracer(Method::Rk45, 0.64);
+    for &b in &[2.8, 3.0, 4.0, 6.0, 10.0, 20.0] {
+        check(&tracer, b, exact(b), 0.01, "exact");
+    }
+}
+
+/// The verlet method steps with explicit Euler, which is only first order:
+/// at the default step it bends rays by too little, the more so the closer
+/// they pass, so it is held to a looser tolerance and kept clear of the
+/// photon sphere.
+#[test]
+fn verlet_matches_exact_deflection() {
+    let tracer = tracer(Method::Verlet, 0.64);
+    for &b in &[6.0, 10.0, 20.0] {
+        check(&tracer, b, exact(b), 0.1, "exact");
+    }
+}
+
+/// The table holds orbits integrated far more finely than the default step,
+/// so it must be at least as close as RK4.
+#[test]
+fn lut_matches_exact_deflection() {
+    let tracer = tracer(Method::Lut, 0.64);
+    for &b in &[2.8, 3.0, 4.0, 6.0, 10.0, 20.0] {
+        check(&tracer, b, exact(b), 0.01, "exact");
+    }
+}
+
+/// Far from the hole the deflection tends to `4M / b`, with a next term of
+/// `15 pi M^2 / 4 b^2`, under 2% of it for these `b`.
+#[test]
+fn deflection_tends_to_weak_field_limit() {
+    for &method in &[Method::Verlet, Method::Rk4, Method::Rk45, Method::Lut] {
+        let tracer = tracer(method, 0.64);
+        for &b in &[100.0, 200.0, 400.0] {
+            check(&tracer, b, 4.0 * M / b, 0.02, "weak field");
+        }
+    }
+}
+
+/// Halving the step of the fixed step integrators must shrink their error
+/// by about 2 to the power of their order: 2 for the first order verlet
+/// method and 16 for RK4.  Verlet is measured further out, as near the
+/// photon sphere its error at these steps is too large to shrink steadily.
+#[test]
+fn fixed_step_integrators_converge() {
+    for &(method, order, b) in &[(Method::Verlet, 1, 6.0), (Method::Rk4, 4, 3.0)] {
+        let exact = exact(b);
+        let coarse = (deflection(&tracer(method, 0.64), b).unwrap() - exact).abs();
+        let fine = (deflection(&tracer(method, 0.32), b).unwrap() - exact).abs();
+        let ratio = coarse / fine;
+        let expected = 2f64.powi(order);
+        assert!(
+            ratio > expected / 2.0,
+            "{:?}: halving the step took the error from {:e} to {:e}, expected a ratio near {}",
+            method,
+            coarse,
+            fine,
+            expected
+        );
+    }
+}
+
+#[test]
+fn rays_inside_critical_impact_parameter_are_captured() {
+    let critical = 3.0 * 3f64.sqrt() * M;
+    for &method in &[Method::Rk4, Method::Rk45, Method::Lut] {
+        let tracer = tracer(method, 0.64);
+        for &b in &[0.0, 0.5, 1.0, 2.0, 2.5, critical - 0.05] {
+            assert!(deflection(&tracer, b).is_none(), "{:?}: ray at b = {} escaped", method, b);
+        }
+        for &b in &[critical + 0.05, 3.0] {
+            assert!(deflection(&tracer, b).is_some(), "{:?}: ray at b = {} was captured", method, b);
+        }
+    }
+}
+
+/// Bending too little, the verlet method lets rays escape from inside the
+/// critical impact parameter, from about `b = 2` at the default step, so only
+/// those passing closer are checked.
+#[test]
+fn verlet_captures_rays_well_inside_critical_impact_parameter() {
+    let tracer = tracer(Method::Verlet, 0.64);
+    for &b in &[0.0, 0.5, 1.0] {
+        assert!(deflection(&tracer, b).is_none(), "ray at b = {} escaped", b);
+    }
+}
+
+fn tracer(method: Method, time_step: f32) -> Tracer {
+    let scene = Scene {
+        method: method,
+        time_step: time_step,
+        disk: Disk::None,
+        bg: Background::Black,
+        ..Scene::default()
+    };
+    Tracer::new(&scene).unwrap()
+}
+
+/// Traces a ray passing the hole at `b` and returns the angle it was bent
+/// by, or `None` if it fell in.
+fn deflection(tracer: &Tracer, b: f64) -> Option<f64> {
+    let origin = vec3(b as f32, 0.0, -R);
+    let (_, aovs) = tracer.trace(origin, origin, vec3(0.0, 0.0, 1.0), 0.0);
+    if aovs[0] == HIT_HORIZON {
+        return None;
+    }
+    assert_eq!(aovs[0], HIT_ESCAPED);
+
+    /* the ray bends towards -x, by more than pi close to the photon sphere */
+    let angle = (-aovs[2] as f64).atan2(aovs[4] as f64);
+    Some(if angle < 0.0 { angle + 2.0 * PI } else { angle })
+}
+
+/// Checks that the deflection at `b` is within `tolerance` of `expected`,
+/// relative to it.
+fn check(tracer: &Tracer, b: f64, expected: f64, tolerance: f64, what: &str) {
+    let angle = deflection(tracer, b).unwrap_or_else(|| panic!("ray at b = {} was captured", b));
+    let error = (angle - expected).abs() / expected;
+    assert!(
+        error < tolerance,
+        "deflection at b = {} is {}, the {} result is {} ({:.2}% off)",
+        b,
+        angle,
+        what,
+        expected,
+        error * 100.0
+    );
+}
+
+/// The exact deflection at `b`, in terms of the closest approach `r0`:
+///
+/// `-pi + 4 sqrt(r0 / Q) (K(k) - F(phi, k))`
+///
+/// where `Q^2 = (r0 - 2M)(r0 + 6M)`, `k^2 = (Q - r0 + 6M) / 2Q` and
+/// `sin^2 phi = (Q - r0 + 2M) / (Q - r0 + 6M)`.
+fn exact(b: f64) -> f64 {
+    /* the largest root of r^3 - b^2 r + 2M b^2 */
+    let r0 = 2.0 * b / 3f64.sqrt() * ((-3.0 * 3f64.sqrt() * M / b).acos() / 3.0).cos();
+    let q = ((r0 - 2.0 * M) * (r0 + 6.0 * M)).sqrt();
+    let k = ((q - r0 + 6.0 * M) / (2.0 * q)).sqrt();
+    let phi = ((q - r0 + 2.0 * M) / (q - r0 + 6.0 * M)).sqrt().asin();
+    -PI + 4.0 * (r0 / q).sqrt() * (elliptic_f(PI / 2.0, k) - elliptic_f(phi, k))
+}
+
+/// The incomplete elliptic integral of the first kind, by Simpson's rule,
+/// which converges quickly for `k < 1` as the integrand is smooth.
+fn elliptic_f(phi: f64, k: f64) -> f64 {
+    let n = 2000;
+    let h = phi / n as f64;
+    let f = |t: f64| 1.0 / (1.0 - k * k * t.sin().powi(2)).sqrt();
+    let odd: f64 = (1..n).step_by(2).map(|i| f(i as f64 * h)).sum();
+    let even: f64 = (2..n).step_by(2).map(|i| f(i as f64 * h)).sum();
+    h / 3.0 * (f(0.0) + 4.0 * odd + 2.0 * even + f(phi))
+}
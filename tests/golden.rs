@@ -0,0 +1,245 @@
+//! Renders a set of canonical scenes at low resolution and compares them with
+//! the reference images in `tests/golden`, so that changes to the generated
+//! shaders or to the CPU tracer can't alter the output unnoticed.
+//!
+//! Every scene is rendered on the GPU, through software GL on machines
+//! without one, and with the CPU tracer, each against references of its own.
+//! The GPU renders need a GL context, which not every machine running the
+//! tests can create, so they only run when asked for with
+//! `cargo test -- --ignored`, and then fail without one.
+//! A failed comparison writes the render and an image of where it differs to
+//! `target/tmp/golden`.  After a deliberate change to the output, run the
+//! tests with `GOLDEN_BLESS=1` to rewrite the references.
+//!
+//! The AOVs written beside the images are checked for their layout only.
+
+extern crate cgmath;
+extern crate gr_trace;
+extern crate image;
+
+use cgmath::vec3;
+use image::{Rgba, RgbaImage};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+
+use gr_trace::output::CHANNELS;
+use gr_trace::scene::{Disk, Method, Stereo, Surface};
+use gr_trace::{render, Camera, Renderer, Scene, Tracer};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+/// CIE76 colour difference beyond which a pixel counts as changed.  About
+/// 2.3 is just noticeable; differences in texture filtering between drivers
+/// stay below this in all but a few pixels.
+const MAX_DELTA_E: f32 = 10.0;
+/// Fraction of the pixels allowed to change, for the few rays skimming the
+/// photon sphere that rounding differences between drivers send elsewhere.
+const MAX_CHANGED: f32 = 0.01;
+
+#[test]
+#[ignore = "needs a GL context, run with --ignored"]
+fn gpu_matches_references() {
+    let display = render::headless().unwrap_or_else(|e| panic!("{}", e));
+    check("gpu", |scene| {
+        let renderer = Renderer::new(&display, scene).unwrap();
+        renderer.render_image(&display, scene.dims(), &camera(), scene.time).unwrap().image
+    });
+}
+
+#[test]
+fn cpu_matches_references() {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    check("cpu", |scene| {
+        let tracer = Tracer::new(scene).unwrap();
+        tracer.render(scene.dims(), &camera(), scene.time, threads).image
+    });
+}
+
+/// A stereo image whose width doesn't split evenly between the eyes leaves a
+/// column neither traces, whose AOVs must still be there, all zero.
+#[test]
+fn cpu_aovs_pad_odd_stereo_widths() {
+    let scene = Scene {
+        width: 33,
+        height: 8,
+        stereo: Stereo::SideBySide,
+        aov: true,
+        iter: 100,
+        ..Scene::default()
+    };
+    let frame = Tracer::new(&scene).unwrap().render(scene.dims(), &camera(), 0.0, 2);
+
+    let aovs = frame.aovs.expect("no AOVs rendered");
+    assert_eq!((aovs.width, aovs.height), scene.dims());
+    assert_eq!(aovs.data.len(), (33 * 8) as usize * CHANNELS.len());
+    for y in 0..8 {
+        /* every pixel of both eyes takes at least one step, the last column
+         * none */
+        for x in 0..32 {
+            assert!(aovs.pixel(x, y)[1] > 0.0, "pixel ({}, {}) was not traced", x, y);
+        }
+        assert!(aovs.pixel(32, y).iter().all(|&c| c == 0.0), "row {} of the gap", y);
+        assert_eq!(frame.image.get_pixel(32, y)[3], 0);
+    }
+}
+
+/// Every tracing method with each disk and surface.
+fn scenes() -> Vec<(String, Scene)> {
+    let methods = [
+        ("flat", Method::Flat),
+        ("verlet", Method::Verlet),
+        ("rk4", Method::Rk4),
+        ("lut", Method::Lut),
+    ];
+    let disks = [
+        ("none", Disk::None),
+        ("white", Disk::White),
+        ("img", Disk::Img),
+        ("dyno", Disk::Dyno),
+        ("blackbody", Disk::Blackbody),
+    ];
+    let surfaces = [("black", Surface::Black), ("checkered", Surface::Checkered)];
+
+    let mut scenes = Vec::new();
+    for &(m, method) in &methods {
+        for &(d, disk) in &disks {
+            for &(s, surface) in &surfaces {
+                let scene = Scene {
+                    width: WIDTH,
+                    height: HEIGHT,
+                    method: method,
+                    disk: disk,
+                    surface: surface,
+                    ..Scene::default()
+                };
+                scenes.push((format!("{}-{}-{}", m, d, s), scene));
+            }
+        }
+    }
+    scenes
+}
+
+/// Slightly above the disk, so that both its near side and the image of its
+/// far side bent over the hole are in view.
+fn camera() -> Camera {
+    Camera::look_at(vec3(0.0, 1.0, -10.0), vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0))
+}
+
+/// Renders every scene with `render` and compares it with its reference in
+/// `tests/golden/<backend>`, failing once all of them have been compared if
+/// any differ.
+fn check<F>(backend: &str, render: F)
+where
+    F: Fn(&Scene) -> RgbaImage,
+{
+    let refs = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(backend);
+    let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden").join(backend);
+    let bless = env::var_os("GOLDEN_BLESS").is_some();
+
+    let mut failed = Vec::new();
+    for (name, scene) in scenes() {
+        let image = render(&scene);
+        let file = format!("{}.png", name);
+        if bless {
+            fs::create_dir_all(&refs).unwrap();
+            image.save(refs.join(&file)).unwrap();
+            continue;
+        }
+
+        let reference = match image::open(refs.join(&file)) {
+            Ok(reference) => reference.to_rgba8(),
+            Err(e) => {
+                failed.push(format!("{}: no reference: {}", name, e));
+                continue;
+            }
+        };
+        if let Err((message, diff)) = compare(&image, &reference) {
+            fs::create_dir_all(&out).unwrap();
+            image.save(out.join(&file)).unwrap();
+            if let Some(diff) = diff {
+                diff.save(out.join(format!("{}.diff.png", name))).unwrap();
+            }
+            failed.push(format!("{}: {}", name, message));
+        }
+    }
+
+    assert!(
+        failed.is_empty(),
+        "{} {} renders differ from their references, see {}:\n{}",
+        failed.len(),
+        backend,
+        out.display(),
+        failed.join("\n")
+    );
+}
+
+/// Checks that no more than `MAX_CHANGED` of the pixels of `image` differ
+/// visibly from `reference`.  Otherwise gives what is wrong, along with an
+/// image of the reference darkened with the changed pixels in red if the
+/// sizes match.
+fn compare(image: &RgbaImage, reference: &RgbaImage) -> Result<(), (String, Option<RgbaImage>)> {
+    if image.dimensions() != reference.dimensions() {
+        let message = format!("size {:?}, expected {:?}", image.dimensions(), reference.dimensions());
+        return Err((message, None));
+    }
+
+    let mut changed = 0;
+    let mut worst = 0.0f32;
+    let mut diff = RgbaImage::new(image.width(), image.height());
+    for (x, y, &p) in reference.enumerate_pixels() {
+        let d = delta_e(*image.get_pixel(x, y), p);
+        let grey = (lab(p)[0] * 0.8) as u8;
+        let out = if d > MAX_DELTA_E {
+            changed += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([grey, grey, grey, 255])
+        };
+        diff.put_pixel(x, y, out);
+        worst = worst.max(d);
+    }
+
+    let fraction = changed as f32 / (image.width() * image.height()) as f32;
+    if fraction > MAX_CHANGED {
+        let message = format!("{:.1}% of pixels changed, by up to {:.1}", fraction * 100.0, worst);
+        Err((message, Some(diff)))
+    } else {
+        Ok(())
+    }
+}
+
+fn delta_e(a: Rgba<u8>, b: Rgba<u8>) -> f32 {
+    let (a, b) = (lab(a), lab(b));
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// CIELAB coordinates of an sRGB colour, under the D65 white point.
+fn lab(c: Rgba<u8>) -> [f32; 3] {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(c[0]), linear(c[1]), linear(c[2]));
+
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
@@ -0,0 +1,59 @@
+//! Checks that the CPU tracer counts the images of the disk and weights
+//! each by `order_weights`.
+//!
+//! The ray traced passes just below the hole from a camera slightly above
+//! the disk.  It crosses the disk in front of the hole, forming the direct
+//! image, then bends around the hole and crosses it again behind, forming
+//! the first photon ring, before escaping.
+
+extern crate cgmath;
+extern crate gr_trace;
+
+use cgmath::vec3;
+
+use gr_trace::output::{HIT_DISK, HIT_ESCAPED};
+use gr_trace::scene::{Background, Disk, Method};
+use gr_trace::{Scene, Tracer};
+
+#[test]
+fn direct_image_hides_the_rings() {
+    for &method in &[Method::Rk4, Method::Lut] {
+        assert_eq!(trace(method, vec![1.0]), (HIT_DISK, 0.0), "{:?}", method);
+    }
+}
+
+/// With the direct image transparent the ray goes on to the first ring, and
+/// with that transparent too it escapes.
+#[test]
+fn zero_weights_see_through_an_image() {
+    for &method in &[Method::Rk4, Method::Lut] {
+        assert_eq!(trace(method, vec![0.0, 1.0, 0.0]), (HIT_DISK, 1.0), "{:?}", method);
+        assert_eq!(trace(method, vec![0.0, 0.0, 1.0]), (HIT_ESCAPED, -1.0), "{:?}", method);
+    }
+}
+
+/// The tracer validates the scene rather than indexing an empty list.
+#[test]
+fn empty_weights_are_rejected() {
+    let scene = Scene {
+        order_weights: Vec::new(),
+        ..Scene::default()
+    };
+    assert!(Tracer::new(&scene).is_err());
+}
+
+/// Traces the ray and returns what it hit and the order of the image it saw
+/// the disk in.
+fn trace(method: Method, order_weights: Vec<f32>) -> (f32, f32) {
+    let scene = Scene {
+        method: method,
+        disk: Disk::White,
+        bg: Background::Black,
+        order_weights: order_weights,
+        ..Scene::default()
+    };
+    let tracer = Tracer::new(&scene).unwrap();
+    let origin = vec3(0.0, 1.0, -10.0);
+    let (_, aovs) = tracer.trace(origin, origin, vec3(0.0, -0.48, 1.0), 0.0);
+    (aovs[0], aovs[9])
+}